@@ -0,0 +1,195 @@
+//!
+//! Answers given ahead of time, for running without a human in front of a terminal
+//!
+//! An answers file maps the `out` variable of an interaction, or the action name, to an answer:
+//!
+//! ```yaml
+//! city: tlv           # input: a string
+//! confirm-action: yes # confirm: a boolean
+//! transport: train    # select: one of the options
+//! langs: [rust, go]   # multiselect: a list of options
+//! ```
+//!
+//! Answers can also come from environment variables ([`EnvAnswers`]), or from
+//! `key=value` pairs ([`Answers::from_pairs`]), where values are strings: `true`/`false`
+//! for confirm, and options joined with the separator for multiselect.
+//!
+use crate::data::EnvExport;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+///
+/// A source of answers to interactions, consulted before prompting
+///
+pub trait AnswerSource {
+    /// answer for `key`, which is either an interaction `out` variable or an action name
+    fn get(&self, key: &str) -> Option<Value>;
+}
+
+///
+/// Answers from a map, usually loaded from a YAML or JSON file
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Answers {
+    answers: BTreeMap<String, Value>,
+}
+
+impl Answers {
+    /// create from a map of answers
+    pub fn new(answers: BTreeMap<String, Value>) -> Self {
+        Self { answers }
+    }
+
+    /// Parse answers from YAML. JSON is valid YAML, so this reads both.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the text is not a map of answers
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        Ok(Self::new(serde_yaml::from_str(text)?))
+    }
+
+    /// Load answers from a YAML or JSON file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or parsed
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read answers file '{}'", path.display()))?;
+        Self::from_yaml(&text)
+            .with_context(|| format!("cannot parse answers file '{}'", path.display()))
+    }
+
+    /// Parse `key=value` pairs, as given on a command line
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a pair has no `=`
+    pub fn from_pairs<I, S>(pairs: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        pairs
+            .into_iter()
+            .map(|pair| {
+                let pair = pair.as_ref();
+                pair.split_once('=')
+                    .map(|(k, v)| (k.trim().to_string(), Value::from(v)))
+                    .ok_or_else(|| anyhow::anyhow!("expected key=value, got '{}'", pair))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+            .map(Self::new)
+    }
+
+    /// set an answer
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        self.answers.insert(key, value)
+    }
+}
+
+impl AnswerSource for Answers {
+    fn get(&self, key: &str) -> Option<Value> {
+        self.answers.get(key).cloned()
+    }
+}
+
+///
+/// Answers from environment variables, named by `naming`: with a prefix of `IA_`
+/// the answer for `city` is taken from `IA_CITY`
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvAnswers {
+    /// how keys are turned into environment variable names
+    pub naming: EnvExport,
+
+    /// variables to read instead of the process environment, see [`EnvAnswers::with_vars`]
+    pub vars: Option<BTreeMap<String, String>>,
+}
+
+impl EnvAnswers {
+    /// create with a prefix for environment variable names, e.g. `IA_`
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            naming: EnvExport {
+                prefix: prefix.to_string(),
+                ..EnvExport::default()
+            },
+            vars: None,
+        }
+    }
+
+    /// create with a prefix, reading `vars` rather than the process environment
+    pub fn with_vars(prefix: &str, vars: BTreeMap<String, String>) -> Self {
+        Self {
+            vars: Some(vars),
+            ..Self::with_prefix(prefix)
+        }
+    }
+}
+
+impl AnswerSource for EnvAnswers {
+    fn get(&self, key: &str) -> Option<Value> {
+        let name = self.naming.name(key);
+        match self.vars {
+            Some(ref vars) => vars.get(&name).cloned(),
+            None => std::env::var(name).ok(),
+        }
+        .map(Value::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_from_yaml() {
+        let answers = Answers::from_yaml(
+            r"
+city: tlv
+confirm-action: true
+langs: [rust, go]
+",
+        )
+        .unwrap();
+        assert_eq!(answers.get("city"), Some(Value::from("tlv")));
+        assert_eq!(answers.get("confirm-action"), Some(Value::from(true)));
+        assert_eq!(answers.get("langs"), Some(Value::from(vec!["rust", "go"])));
+        assert_eq!(answers.get("missing"), None);
+
+        let answers = Answers::from_yaml(r#"{"city": "tlv"}"#).unwrap();
+        assert_eq!(answers.get("city"), Some(Value::from("tlv")));
+
+        assert!(Answers::from_yaml("- a list").is_err());
+    }
+
+    #[test]
+    fn test_from_pairs() {
+        let answers = Answers::from_pairs(["city=tel aviv", "expr=a=b"]).unwrap();
+        assert_eq!(answers.get("city"), Some(Value::from("tel aviv")));
+        assert_eq!(answers.get("expr"), Some(Value::from("a=b")));
+        assert!(Answers::from_pairs(["city"]).is_err());
+    }
+
+    #[test]
+    fn test_env_answers() {
+        let answers = EnvAnswers::with_vars(
+            "ia_",
+            BTreeMap::from([("IA_CITY".to_string(), "tlv".to_string())]),
+        );
+        assert_eq!(answers.get("city"), Some(Value::from("tlv")));
+        assert_eq!(answers.get("transport"), None);
+
+        // cargo sets it for every test run
+        let answers = EnvAnswers::with_prefix("cargo_pkg_");
+        assert_eq!(
+            answers.get("name"),
+            Some(Value::from("interactive-actions"))
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//!
+//! Checkpoints of a run, so a run which failed midway can be resumed
+//!
+//! ```no_run
+//! use interactive_actions::checkpoint::CheckpointStore;
+//! use interactive_actions::data::{Action, ActionHook, VarBag};
+//! use interactive_actions::ActionRunner;
+//! use std::path::Path;
+//!
+//! # let actions: Vec<Action> = vec![];
+//! let store = CheckpointStore::new(Path::new(".setup-checkpoint.json"));
+//! let mut runner = ActionRunner {
+//!     // skip what a previous run completed. fails if the actions changed since
+//!     resume: store.load_for(&actions, &ActionHook::After).unwrap(),
+//!     checkpoint: Some(store),
+//!     ..ActionRunner::default()
+//! };
+//! let mut v = VarBag::new();
+//! runner.run(&actions, None, &mut v, ActionHook::After, None::<fn(&Action) -> ()>);
+//! ```
+//!
+use crate::data::{Action, ActionHook, ActionResult, VarBag};
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+///
+/// Progress of a run: what completed, and the variables after the last completed action
+///
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// hash of the action definitions this checkpoint was recorded for, see [`definitions_hash`]
+    pub definitions: String,
+    /// names of the completed actions, in order
+    pub completed: Vec<String>,
+    /// results of the completed actions, in order
+    pub results: Vec<ActionResult>,
+    /// variables after the last completed action. secret variables are not stored,
+    /// so on resume, completed actions which set them ask for them again, without
+    /// running their script
+    pub varbag: VarBag,
+}
+
+impl Checkpoint {
+    /// empty checkpoint for a set of actions
+    pub fn new(actions: &[Action], hook: &ActionHook) -> Self {
+        Self {
+            definitions: definitions_hash(actions, hook),
+            ..Self::default()
+        }
+    }
+
+    /// were the actions changed since this checkpoint was recorded
+    pub fn is_stale(&self, actions: &[Action], hook: &ActionHook) -> bool {
+        self.definitions != definitions_hash(actions, hook)
+    }
+}
+
+/// Stable hash of the actions which run in `hook`, to detect changes in definitions
+pub fn definitions_hash(actions: &[Action], hook: &ActionHook) -> String {
+    let actions = actions
+        .iter()
+        .filter(|action| &action.hook == hook)
+        .collect::<Vec<_>>();
+    let json = serde_json::to_string(&(hook, actions)).unwrap_or_default();
+    // FNV-1a, stable across platforms and releases
+    let hash = json.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+///
+/// Stores a [`Checkpoint`] in a file, as JSON if the file name ends with `.json`, and YAML otherwise
+///
+#[derive(Clone, Debug)]
+pub struct CheckpointStore {
+    path: PathBuf,
+}
+
+impl CheckpointStore {
+    /// create a store in the given file
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// path of the checkpoint file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn is_json(&self) -> bool {
+        self.path.extension().is_some_and(|ext| ext == "json")
+    }
+
+    /// Load the stored checkpoint, if there is one. It may be stale, which
+    /// [`ActionRunner`](crate::ActionRunner) checks before resuming it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or parsed
+    pub fn load(&self) -> Result<Option<Checkpoint>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("cannot read checkpoint '{}'", self.path.display()))?;
+        let checkpoint = if self.is_json() {
+            serde_json::from_str(&text)?
+        } else {
+            serde_yaml::from_str(&text)?
+        };
+        Ok(Some(checkpoint))
+    }
+
+    /// Load the stored checkpoint for resuming `actions`, if there is one
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the checkpoint is stale: it was
+    /// recorded for different action definitions
+    pub fn load_for(&self, actions: &[Action], hook: &ActionHook) -> Result<Option<Checkpoint>> {
+        match self.load()? {
+            Some(checkpoint) if checkpoint.is_stale(actions, hook) => anyhow::bail!(
+                "checkpoint '{}' is stale: actions changed since it was recorded",
+                self.path.display()
+            ),
+            checkpoint => Ok(checkpoint),
+        }
+    }
+
+    /// Store a checkpoint, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be written
+    pub fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        let text = if self.is_json() {
+            serde_json::to_string_pretty(checkpoint)?
+        } else {
+            serde_yaml::to_string(checkpoint)?
+        };
+        // write aside and rename, so a crash never leaves a half written checkpoint
+        let tmp = self.temp_path();
+        std::fs::write(&tmp, text)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .with_context(|| format!("cannot write checkpoint '{}'", self.path.display()))
+    }
+
+    /// `state.json` -> `state.json.tmp`, so stores differing only by format do not share it
+    fn temp_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        self.path.with_file_name(name)
+    }
+
+    /// Remove the stored checkpoint, if there is one
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be removed
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)
+                .with_context(|| format!("cannot remove checkpoint '{}'", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn actions(run: &str) -> Vec<Action> {
+        serde_yaml::from_str(&format!(
+            r#"
+- name: first
+  run: {run}
+- name: setup
+  hook: before
+  run: echo setup
+"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stale() {
+        let checkpoint = Checkpoint::new(&actions("echo 1"), &ActionHook::After);
+        assert!(!checkpoint.is_stale(&actions("echo 1"), &ActionHook::After));
+        assert!(checkpoint.is_stale(&actions("echo 2"), &ActionHook::After));
+        assert!(checkpoint.is_stale(&actions("echo 1"), &ActionHook::Before));
+    }
+
+    #[test]
+    fn test_store() {
+        for ext in ["json", "yaml"] {
+            let path = std::env::temp_dir().join(format!(
+                "interactive-actions-test-store-{}.{ext}",
+                std::process::id()
+            ));
+            let store = CheckpointStore::new(&path);
+            store.clear().unwrap();
+            assert!(store.load().unwrap().is_none());
+
+            let mut checkpoint = Checkpoint::new(&actions("echo 1"), &ActionHook::After);
+            checkpoint.completed.push("first".to_string());
+            checkpoint
+                .varbag
+                .insert("city".to_string(), "tlv".to_string());
+            checkpoint
+                .varbag
+                .insert_secret("token".to_string(), "s3cr3t".to_string());
+            store.save(&checkpoint).unwrap();
+
+            let loaded = store
+                .load_for(&actions("echo 1"), &ActionHook::After)
+                .unwrap()
+                .unwrap();
+            assert_eq!(loaded.completed, vec!["first"]);
+            assert_eq!(loaded.varbag.get("city").map(String::as_str), Some("tlv"));
+            assert_eq!(loaded.varbag.get("token"), None);
+            assert!(store
+                .load_for(&actions("echo 2"), &ActionHook::After)
+                .is_err());
+
+            store.clear().unwrap();
+            assert!(!path.exists());
+        }
+        assert_eq!(
+            CheckpointStore::new(Path::new("state.json")).temp_path(),
+            PathBuf::from("state.json.tmp")
+        );
+        assert_ne!(
+            CheckpointStore::new(Path::new("state.json")).temp_path(),
+            CheckpointStore::new(Path::new("state.yaml")).temp_path()
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//!
+//! `when` expressions, deciding if an [`Action`](crate::data::Action) should run
+//!
+//! An expression is evaluated against the variables captured so far:
+//!
+//! ```text
+//! city == "tlv" and transport != "bus"
+//! transport in ["bus", "train"] or not confirm
+//! city is not empty
+//! ```
+//!
+//! A bare variable is true when it is set, non-empty and not `"false"`. Missing
+//! variables evaluate to an empty string.
+//!
+use crate::data::VarBag;
+use anyhow::{bail, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Var(String),
+    Lit(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Eq(Operand, Operand),
+    In(Operand, Vec<Operand>),
+    Empty(Operand),
+    Truthy(Operand),
+}
+
+///
+/// A parsed `when` expression
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    expr: Expr,
+}
+
+const KEYWORDS: &[&str] = &["and", "or", "not", "in", "is", "empty", "true", "false"];
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '[' | ']' | ',' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    _ => Token::Comma,
+                });
+            }
+            '=' | '!' => {
+                chars.next();
+                if chars.next() != Some('=') {
+                    bail!("expected '{c}=' in '{input}'");
+                }
+                tokens.push(if c == '=' { Token::Eq } else { Token::Ne });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') => {
+                            if let Some(escaped) = chars.next() {
+                                s.push(escaped);
+                            }
+                        }
+                        Some(ch) => s.push(ch),
+                        None => bail!("unterminated string in '{input}'"),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_alphanumeric() || ch == '_' || ch == '-' || ch == '.' {
+                        s.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(s));
+            }
+            _ => bail!("unexpected character '{c}' in '{input}'"),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident == keyword)
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        match self.next() {
+            Some(ref t) if t == token => Ok(()),
+            other => bail!("expected {token:?}, found {other:?}"),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.is_keyword("or") {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.is_keyword("and") {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.is_keyword("not") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::LParen) {
+            self.next();
+            let expr = self.or()?;
+            self.expect(&Token::RParen)?;
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        let expr = match self.peek() {
+            Some(Token::Eq) => {
+                self.next();
+                Expr::Eq(left, self.operand()?)
+            }
+            Some(Token::Ne) => {
+                self.next();
+                Expr::Not(Box::new(Expr::Eq(left, self.operand()?)))
+            }
+            Some(Token::Ident(ident)) if ident == "in" => {
+                self.next();
+                Expr::In(left, self.list()?)
+            }
+            Some(Token::Ident(ident)) if ident == "not" => {
+                self.next();
+                if !self.is_keyword("in") {
+                    bail!("expected 'in' after 'not'");
+                }
+                self.next();
+                Expr::Not(Box::new(Expr::In(left, self.list()?)))
+            }
+            Some(Token::Ident(ident)) if ident == "is" => {
+                self.next();
+                let negate = self.is_keyword("not");
+                if negate {
+                    self.next();
+                }
+                if !self.is_keyword("empty") {
+                    bail!("expected 'empty' after 'is'");
+                }
+                self.next();
+                if negate {
+                    Expr::Not(Box::new(Expr::Empty(left)))
+                } else {
+                    Expr::Empty(left)
+                }
+            }
+            _ => Expr::Truthy(left),
+        };
+        Ok(expr)
+    }
+
+    fn list(&mut self) -> Result<Vec<Operand>> {
+        self.expect(&Token::LBracket)?;
+        let mut items = vec![];
+        if self.peek() == Some(&Token::RBracket) {
+            self.next();
+            return Ok(items);
+        }
+        loop {
+            items.push(self.operand()?);
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RBracket) => return Ok(items),
+                other => bail!("expected ',' or ']', found {other:?}"),
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Operand::Lit(s)),
+            Some(Token::Ident(ident)) if ident == "true" || ident == "false" => {
+                Ok(Operand::Lit(ident))
+            }
+            Some(Token::Ident(ident)) if KEYWORDS.contains(&ident.as_str()) => {
+                bail!("unexpected keyword '{ident}'")
+            }
+            Some(Token::Ident(ident)) if ident.starts_with(|c: char| c.is_ascii_digit()) => {
+                Ok(Operand::Lit(ident))
+            }
+            Some(Token::Ident(ident)) => Ok(Operand::Var(ident)),
+            other => bail!("expected a variable or a value, found {other:?}"),
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, varbag: &'a VarBag) -> &'a str {
+        match self {
+            Self::Var(name) => varbag.get(name).map_or("", String::as_str),
+            Self::Lit(value) => value,
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, varbag: &VarBag) -> bool {
+        match self {
+            Self::Or(left, right) => left.eval(varbag) || right.eval(varbag),
+            Self::And(left, right) => left.eval(varbag) && right.eval(varbag),
+            Self::Not(expr) => !expr.eval(varbag),
+            Self::Eq(left, right) => left.value(varbag) == right.value(varbag),
+            Self::In(left, items) => {
+                let value = left.value(varbag);
+                items.iter().any(|item| item.value(varbag) == value)
+            }
+            Self::Empty(operand) => operand.value(varbag).trim().is_empty(),
+            Self::Truthy(operand) => {
+                let value = operand.value(varbag).trim();
+                !value.is_empty() && value != "false"
+            }
+        }
+    }
+
+    fn variables<'a>(&'a self, vars: &mut Vec<&'a str>) {
+        let mut push = |operand: &'a Operand| {
+            if let Operand::Var(name) = operand {
+                vars.push(name);
+            }
+        };
+        match self {
+            Self::Or(left, right) | Self::And(left, right) => {
+                left.variables(vars);
+                right.variables(vars);
+            }
+            Self::Not(expr) => expr.variables(vars),
+            Self::Eq(left, right) => {
+                push(left);
+                push(right);
+            }
+            Self::In(left, items) => {
+                push(left);
+                items.iter().for_each(push);
+            }
+            Self::Empty(operand) | Self::Truthy(operand) => push(operand),
+        }
+    }
+}
+
+impl Condition {
+    /// Parse a `when` expression
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the expression is malformed
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        if parser.peek().is_none() {
+            bail!("empty expression");
+        }
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {token:?} in '{input}'");
+        }
+        Ok(Self { expr })
+    }
+
+    /// Evaluate against the given variables
+    pub fn eval(&self, varbag: &VarBag) -> bool {
+        self.expr.eval(varbag)
+    }
+
+    /// Names of the variables this expression refers to
+    pub fn variables(&self) -> Vec<&str> {
+        let mut vars = vec![];
+        self.expr.variables(&mut vars);
+        vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn eval(input: &str) -> bool {
+        let varbag = [
+            ("city", "tlv"),
+            ("transport", "bus"),
+            ("confirm", "false"),
+            ("blank", " "),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<VarBag>();
+        Condition::parse(input).unwrap().eval(&varbag)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert!(eval(r#"city == "tlv""#));
+        assert!(eval("city != 'dallas'"));
+        assert!(eval(r#"transport in ["bus", "train"]"#));
+        assert!(eval(r#"transport not in ["walk"]"#));
+        assert!(eval("not confirm"));
+        assert!(eval("confirm == false"));
+        assert!(eval("missing is empty and city is not empty"));
+        assert!(eval(
+            r#"city == "dallas" or (transport == "bus" and not missing)"#
+        ));
+        assert!(eval("city"));
+        assert!(!eval("not city"));
+        assert!(!eval("confirm"));
+        assert!(!eval("blank"));
+        assert!(!eval(r#"city == "tlv" and transport == "train""#));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Condition::parse("").is_err());
+        assert!(Condition::parse("city ==").is_err());
+        assert!(Condition::parse("city = 'tlv'").is_err());
+        assert!(Condition::parse("(city").is_err());
+        assert!(Condition::parse("city in [a, b").is_err());
+        assert!(Condition::parse("city is set").is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        let condition = Condition::parse("a == b or c in [d, 'e'] and not f").unwrap();
+        assert_eq!(condition.variables(), vec!["a", "b", "c", "d", "f"]);
+    }
+}
@@ -3,19 +3,170 @@
 //!
 use anyhow::Result;
 use requestty::{Answer, Question};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
 
 use requestty_ui::backend::{Size, TestBackend};
 use requestty_ui::events::{KeyEvent, TestEvents};
 use serde_derive::{Deserialize, Serialize};
 use std::vec::IntoIter;
 
-fn default<T: Default + PartialEq>(t: &T) -> bool {
+pub(crate) fn default<T: Default + PartialEq>(t: &T) -> bool {
     *t == Default::default()
 }
 
-#[doc(hidden)]
-pub type VarBag = BTreeMap<String, String>;
+/// placeholder shown instead of secret values
+pub const REDACTED: &str = "********";
+
+///
+/// Variables captured from interactions, keyed by name.
+///
+/// Values inserted with [`VarBag::insert_secret`] are never displayed: they are
+/// masked in `Debug` output, left out when serializing, and can be masked in any
+/// text with [`VarBag::redact`].
+///
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct VarBag {
+    vars: BTreeMap<String, String>,
+    secrets: BTreeSet<String>,
+}
+
+impl VarBag {
+    /// create an empty bag
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// set a variable, returning the previous value if any
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        self.secrets.remove(&key);
+        self.vars.insert(key, value)
+    }
+
+    /// set a variable and mark it as secret
+    pub fn insert_secret(&mut self, key: String, value: String) -> Option<String> {
+        self.secrets.insert(key.clone());
+        self.vars.insert(key, value)
+    }
+
+    /// get the value of a variable
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.vars.get(key)
+    }
+
+    /// is the variable marked as secret
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.secrets.contains(key)
+    }
+
+    /// is the variable set
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.vars.contains_key(key)
+    }
+
+    /// remove a variable
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.secrets.remove(key);
+        self.vars.remove(key)
+    }
+
+    /// iterate over all variables, including secret ones
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, String, String> {
+        self.vars.iter()
+    }
+
+    /// number of variables
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    /// is the bag empty
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// replace every occurrence of a secret value in `text` with [`REDACTED`]
+    pub fn redact(&self, text: &str) -> String {
+        self.secrets
+            .iter()
+            .filter_map(|key| self.vars.get(key))
+            .filter(|value| !value.is_empty())
+            .fold(text.to_string(), |acc, value| acc.replace(value, REDACTED))
+    }
+}
+
+impl<'a> IntoIterator for &'a VarBag {
+    type Item = (&'a String, &'a String);
+    type IntoIter = std::collections::btree_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vars.iter()
+    }
+}
+
+impl FromIterator<(String, String)> for VarBag {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self {
+            vars: iter.into_iter().collect(),
+            secrets: BTreeSet::new(),
+        }
+    }
+}
+
+/// read access to the variables as a map, as when `VarBag` was a `BTreeMap`
+impl std::ops::Deref for VarBag {
+    type Target = BTreeMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vars
+    }
+}
+
+impl From<BTreeMap<String, String>> for VarBag {
+    fn from(vars: BTreeMap<String, String>) -> Self {
+        vars.into_iter().collect()
+    }
+}
+
+/// all variables, secret ones included
+impl From<VarBag> for BTreeMap<String, String> {
+    fn from(varbag: VarBag) -> Self {
+        varbag.vars
+    }
+}
+
+impl fmt::Debug for VarBag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.vars.iter().map(|(k, v)| {
+                if self.is_secret(k) {
+                    (k, REDACTED)
+                } else {
+                    (k, v.as_str())
+                }
+            }))
+            .finish()
+    }
+}
+
+impl serde::Serialize for VarBag {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.vars.iter().filter(|(k, _)| !self.is_secret(k)))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for VarBag {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let vars = <BTreeMap<String, String> as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(vars.into_iter().collect())
+    }
+}
 
 ///
 /// When in the workflow to hook the action
@@ -42,6 +193,11 @@ pub struct Action {
     /// unique name of action
     pub name: String,
 
+    /// only run this action if the expression holds, see [`Condition`](crate::condition::Condition)
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+
     /// interaction
     #[serde(default)]
     pub interaction: Option<Interaction>,
@@ -51,6 +207,41 @@ pub struct Action {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub run: Option<String>,
 
+    /// shell running the script, with the script appended, e.g. `bash -eu -c`. `sh -c` if not set
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+
+    /// working directory of the run script, relative to the one given to the runner
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+
+    /// export variables to the environment of the run script, overrides the runner setting
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export_env: Option<EnvExport>,
+
+    /// environment variables of the run script. values are templates
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// shell-quote every `{{var}}` in the run script, overrides the runner setting
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell_escape: Option<bool>,
+
+    /// run the script as is, without templating, e.g. when it has literal `{{`. overrides the runner setting
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+
+    /// capture the outcome of the run script into variables
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out: Option<ScriptOut>,
+
     /// ignore exit code from the script, otherwise if error then exists
     ///
     #[serde(default)]
@@ -62,16 +253,412 @@ pub struct Action {
     #[serde(skip_serializing_if = "default")]
     pub break_if_cancel: bool,
 
-    /// captures the output of the script, otherwise, stream to screen in real time
+    /// what to do when a confirm interaction is answered with "no"
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
-    pub capture: bool,
+    pub on_decline: DeclinePolicy,
+
+    /// seconds the run script may take before it is killed, along with everything it started
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+
+    /// run the script again when it fails
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<Retry>,
+
+    /// captures the output of the script (`true`), or both streams and captures it (`tee`),
+    /// otherwise (`false`, or not set), stream to screen in real time
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<Capture>,
+
+    /// prefix of every line of output streamed to screen in `tee` mode, e.g. `"[build] "`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_prefix: Option<String>,
+
+    /// most bytes of each of stdout and stderr to keep when capturing, overrides the runner setting.
+    /// beyond it, the start of the output is dropped and replaced with a truncation marker
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_capture: Option<usize>,
 
     /// When to run this action
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub hook: ActionHook,
+
+    /// actions to run when this action fails or a cancel breaks out of it. the failure
+    /// is described by the `failed_action`, `failed_error`, `failed_exit_code` and
+    /// `failed_stderr` variables (stderr only when the output is captured), which are
+    /// set only while the handlers run
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_failure: Vec<Action>,
+
+    /// actions to run after this action, whether it failed or not
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub finally: Vec<Action>,
+}
+///
+/// What to do with the output of a run script: `false`, `true` or `tee`
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Capture {
+    /// stream to screen, nothing is captured
+    #[default]
+    Off,
+    /// capture, nothing is shown until the script ends
+    On,
+    /// stream to screen as it arrives, and capture too
+    Tee,
+}
+
+impl Capture {
+    /// is the output captured
+    pub fn is_captured(self) -> bool {
+        self != Self::Off
+    }
+}
+
+impl serde::Serialize for Capture {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Self::Off => serializer.serialize_bool(false),
+            Self::On => serializer.serialize_bool(true),
+            Self::Tee => serializer.serialize_str("tee"),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Capture {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bool(bool),
+            Mode(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Bool(false) => Ok(Self::Off),
+            Repr::Bool(true) => Ok(Self::On),
+            Repr::Mode(mode) if mode == "tee" => Ok(Self::Tee),
+            Repr::Mode(mode) => Err(serde::de::Error::custom(format!(
+                "expected true, false or tee for capture, got '{mode}'"
+            ))),
+        }
+    }
+}
+
+///
+/// How to retry a failing run script
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Retry {
+    /// how many times to run the script in total
+    #[serde(default = "Retry::default_attempts")]
+    pub attempts: u32,
+
+    /// seconds to wait before the first retry
+    #[serde(default)]
+    pub delay: f64,
+
+    /// multiplier of the delay after every retry, e.g. `2` doubles it
+    #[serde(default = "Retry::default_backoff")]
+    pub backoff: f64,
+
+    /// once attempts run out, ask the user whether to retry, skip or abort
+    #[serde(default)]
+    pub ask: bool,
+
+    /// retry a script which ran into its [`Action::timeout`] too, rather than failing at once
+    #[serde(default)]
+    pub on_timeout: bool,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: Self::default_attempts(),
+            delay: 0.0,
+            backoff: Self::default_backoff(),
+            ask: false,
+            on_timeout: false,
+        }
+    }
+}
+
+impl Retry {
+    fn default_attempts() -> u32 {
+        1
+    }
+
+    fn default_backoff() -> f64 {
+        1.0
+    }
+
+    /// how long to wait before the given retry, counting from 1
+    pub fn delay_before(&self, retry: u32) -> Duration {
+        let exp = i32::try_from(retry.saturating_sub(1)).unwrap_or(i32::MAX);
+        let secs = self.delay * self.backoff.powi(exp);
+        if secs.is_finite() && secs > 0.0 {
+            Duration::from_secs_f64(secs)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+///
+/// How variable names are turned into environment variable names
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum EnvCase {
+    /// `city` -> `CITY`
+    #[default]
+    #[serde(rename = "upper")]
+    Upper,
+
+    /// `city` -> `city`
+    #[serde(rename = "keep")]
+    Keep,
+}
+
+///
+/// Exports [`VarBag`] entries as environment variables of run scripts, so scripts can
+/// refer to `$IA_CITY` instead of splicing `{{city}}` into the script text.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvExport {
+    /// prepended to every name, [`DEFAULT_ENV_PREFIX`] if not set. an empty prefix lets
+    /// variables such as `path` or `home` overwrite `PATH` or `HOME`
+    #[serde(default = "default_env_prefix")]
+    #[serde(skip_serializing_if = "is_default_env_prefix")]
+    pub prefix: String,
+
+    /// case of the exported names
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub case: EnvCase,
+}
+
+/// prefix of exported environment variables, unless set otherwise
+pub const DEFAULT_ENV_PREFIX: &str = "IA_";
+
+fn default_env_prefix() -> String {
+    DEFAULT_ENV_PREFIX.to_string()
+}
+
+fn is_default_env_prefix(prefix: &str) -> bool {
+    prefix == DEFAULT_ENV_PREFIX
+}
+
+impl Default for EnvExport {
+    fn default() -> Self {
+        Self {
+            prefix: default_env_prefix(),
+            case: EnvCase::default(),
+        }
+    }
 }
+
+impl EnvExport {
+    /// environment variable name for a variable: prefixed, cased, and with
+    /// anything other than letters, digits and `_` replaced by `_`
+    pub fn name(&self, var: &str) -> String {
+        let name = format!("{}{}", self.prefix, var)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        match self.case {
+            EnvCase::Upper => name.to_ascii_uppercase(),
+            EnvCase::Keep => name,
+        }
+    }
+
+    /// environment variables for every entry of the varbag
+    pub fn vars(&self, varbag: &VarBag) -> HashMap<String, String> {
+        varbag
+            .iter()
+            .map(|(k, v)| (self.name(k), v.clone()))
+            .collect()
+    }
+}
+
+///
+/// Captures the outcome of a run script into variables, so later actions can use them.
+/// Setting any of these captures the script output instead of streaming it to screen.
+///
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ScriptOut {
+    /// variable to set to the trimmed stdout
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+
+    /// variable to set to the trimmed stderr
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+
+    /// variable to set to the exit code
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+
+    /// values to extract out of stdout
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extract: Vec<Extract>,
+}
+
+///
+/// Extracts a value out of script stdout into a variable. Selectors are applied
+/// in order: `line`, then `regex`, then `json`.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Extract {
+    /// variable to set
+    pub var: String,
+
+    /// pick a single line, zero based. negative values count from the end
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+
+    /// regular expression to match
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+
+    /// capture group of `regex` to take, default is the first group, or the whole match if there are none
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<usize>,
+
+    /// JSON pointer into the text parsed as JSON, e.g. `/package/name`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<String>,
+}
+
+impl Extract {
+    /// Extract a value out of `text`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the selectors does not match
+    pub fn apply(&self, text: &str) -> Result<String> {
+        let mut value = text.trim().to_string();
+        if let Some(line) = self.line {
+            let lines = value.lines().collect::<Vec<_>>();
+            let index = if line < 0 {
+                i64::try_from(lines.len()).unwrap_or(i64::MAX) + line
+            } else {
+                line
+            };
+            value = usize::try_from(index)
+                .ok()
+                .and_then(|i| lines.get(i))
+                .ok_or_else(|| anyhow::anyhow!("'{}': no line {}", self.var, line))?
+                .to_string();
+        }
+        if let Some(ref regex) = self.regex {
+            let re = regex::Regex::new(regex)?;
+            let captures = re
+                .captures(&value)
+                .ok_or_else(|| anyhow::anyhow!("'{}': no match for /{}/", self.var, regex))?;
+            let group = self
+                .group
+                .unwrap_or_else(|| usize::from(captures.len() > 1));
+            value = captures
+                .get(group)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("'{}': no group {} in /{}/", self.var, group, regex)
+                })?
+                .as_str()
+                .to_string();
+        }
+        if let Some(ref pointer) = self.json {
+            let json: serde_json::Value = serde_json::from_str(&value)
+                .map_err(|e| anyhow::anyhow!("'{}': not JSON: {}", self.var, e))?;
+            value = match json.pointer(pointer) {
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+                None => anyhow::bail!("'{}': nothing at {}", self.var, pointer),
+            };
+        }
+        Ok(value)
+    }
+}
+
+impl ScriptOut {
+    /// Set variables out of a script outcome
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an extractor does not match
+    pub fn update_varbag(
+        &self,
+        code: i32,
+        out: &str,
+        err: &str,
+        varbag: &mut VarBag,
+    ) -> Result<()> {
+        if let Some(ref var) = self.stdout {
+            varbag.insert(var.clone(), out.trim().to_string());
+        }
+        if let Some(ref var) = self.stderr {
+            varbag.insert(var.clone(), err.trim().to_string());
+        }
+        if let Some(ref var) = self.code {
+            varbag.insert(var.clone(), code.to_string());
+        }
+        for extract in &self.extract {
+            varbag.insert(extract.var.clone(), extract.apply(out)?);
+        }
+        Ok(())
+    }
+}
+
+///
+/// What to do when a confirm interaction is declined
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum DeclinePolicy {
+    /// Treat as a cancel: skip the run script, and break out if `break_if_cancel` is set
+    #[default]
+    #[serde(rename = "cancel")]
+    Cancel,
+
+    /// Skip the run script of this action only, and continue with the rest
+    #[serde(rename = "skip")]
+    Skip,
+
+    /// Stop running the rest of the actions
+    #[serde(rename = "break")]
+    Break,
+}
+
+impl DeclinePolicy {
+    /// should a decline stop the rest of the actions
+    pub fn breaks(&self, break_if_cancel: bool) -> bool {
+        match self {
+            Self::Cancel => break_if_cancel,
+            Self::Skip => false,
+            Self::Break => true,
+        }
+    }
+}
+
 ///
 /// result of the [`Action`]
 ///
@@ -83,15 +670,135 @@ pub struct ActionResult {
     pub run: Option<RunResult>,
     /// interaction response, if any
     pub response: Response,
+    /// how the action ended
+    #[serde(default)]
+    pub status: ActionStatus,
+    /// earlier runs of the script which failed and were retried, in order. `run` is the last one
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<RunResult>,
+    /// when the action started and ended, from its interaction to its script
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+    /// when the interaction started and ended, including the time the user took to answer
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interaction_timing: Option<Timing>,
+}
+
+impl ActionResult {
+    /// result of an action which did nothing yet
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            run: None,
+            response: Response::None,
+            status: ActionStatus::Ok,
+            attempts: vec![],
+            timing: None,
+            interaction_timing: None,
+        }
+    }
+}
+
+///
+/// When something started and ended
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timing {
+    /// start time, RFC 3339 in UTC
+    pub started_at: String,
+    /// end time, RFC 3339 in UTC
+    pub ended_at: String,
+    /// milliseconds from start to end
+    pub duration_ms: u64,
+}
+
+///
+/// Measures a [`Timing`]
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Stopwatch {
+    at: SystemTime,
+    started: Instant,
+}
+
+impl Stopwatch {
+    /// start measuring
+    pub fn start() -> Self {
+        Self {
+            at: SystemTime::now(),
+            started: Instant::now(),
+        }
+    }
+
+    /// timing from the start until now
+    pub fn stop(&self) -> Timing {
+        let elapsed = self.started.elapsed();
+        Timing {
+            started_at: humantime::format_rfc3339_millis(self.at).to_string(),
+            ended_at: humantime::format_rfc3339_millis(self.at + elapsed).to_string(),
+            duration_ms: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+///
+/// How an [`Action`] ended
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ActionStatus {
+    /// The action ran
+    #[default]
+    #[serde(rename = "ok")]
+    Ok,
+
+    /// The interaction was cancelled, or its confirm declined, and the action does not break on it
+    #[serde(rename = "cancelled")]
+    Cancelled,
+
+    /// The action failed, or a cancel broke out of it, see [`RunReport::failed`](crate::report::RunReport::failed)
+    #[serde(rename = "failed")]
+    Failed,
+
+    /// The script returned a non-zero exit code, which the action ignores
+    #[serde(rename = "ignored_failure")]
+    IgnoredFailure,
+
+    /// The action was skipped because its `when` expression did not hold, its confirm
+    /// was declined with `on_decline: skip`, or its script failed and the user chose to skip it
+    #[serde(rename = "skipped")]
+    Skipped,
+
+    /// Dry run: the action was resolved, and has no script to run
+    #[serde(rename = "planned")]
+    Planned,
+
+    /// Dry run: the script was rendered, and would have run
+    #[serde(rename = "would_run")]
+    WouldRun,
 }
 
 #[allow(missing_docs)]
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct RunResult {
     pub script: String,
     pub code: i32,
     pub out: String,
     pub err: String,
+    /// when the script started and ended
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+    /// working directory the script ran in, the current one if not set
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// variables exported to the environment of the script, on top of the inherited ones
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
 }
 
 #[allow(missing_docs)]
@@ -103,12 +810,18 @@ pub enum InteractionKind {
     Input,
     #[serde(rename = "select")]
     Select,
+    #[serde(rename = "multiselect")]
+    MultiSelect,
+    #[serde(rename = "password")]
+    Password,
 }
 
 #[allow(missing_docs)]
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Response {
     Text(String),
+    List(Vec<String>),
+    Bool(bool),
     Cancel,
     None,
 }
@@ -127,7 +840,7 @@ pub struct Interaction {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub out: Option<String>,
 
-    /// define the set of options just for kind=select
+    /// define the set of options just for kind=select and kind=multiselect
     #[serde(skip_serializing_if = "Option::is_none")]
     pub options: Option<Vec<String>>,
 
@@ -138,6 +851,10 @@ pub struct Interaction {
     /// perform this interaction even if default is supplied, default is to skip
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ask_if_has_default: Option<bool>,
+
+    /// separator used to join multiselect answers when stored in a variable, default is `,`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
 }
 
 /// default value of interaction, depending on the type of interaction
@@ -150,14 +867,20 @@ pub enum DefaultValue {
     Select(usize),
     /// default value for confirm - true or false
     Confirm(bool),
+    /// default value for multiselect - indices of the selected options
+    MultiSelect(Vec<usize>),
 }
 
 impl Interaction {
     fn update_varbag(&self, input: &str, varbag: Option<&mut VarBag>) {
         varbag.map(|bag| {
-            self.out
-                .as_ref()
-                .map(|out| bag.insert(out.to_string(), input.to_string()))
+            self.out.as_ref().map(|out| {
+                if matches!(self.kind, InteractionKind::Password) {
+                    bag.insert_secret(out.to_string(), input.to_string())
+                } else {
+                    bag.insert(out.to_string(), input.to_string())
+                }
+            })
         });
     }
 
@@ -175,9 +898,10 @@ impl Interaction {
         let mut prompt = requestty::PromptModule::new([question]);
         let answer = self.to_default_answer();
         if let Some(answer) = answer {
-            prompt = prompt.with_answers(requestty::Answers::from_iter(
-                [("question".to_string(), answer)].into_iter(),
-            ));
+            prompt = prompt.with_answers(requestty::Answers::from_iter([(
+                "question".to_string(),
+                answer,
+            )]));
         }
 
         if let Some(events) = events {
@@ -188,11 +912,19 @@ impl Interaction {
         }?;
 
         let answers = prompt.into_answers();
-        let answer = answers.get("question");
+        Ok(self.respond(answers.get("question"), varbag))
+    }
 
-        Ok(match answer {
+    /// Turn an answer into a [`Response`], capturing it into `varbag`
+    pub fn respond(&self, answer: Option<&Answer>, varbag: Option<&mut VarBag>) -> Response {
+        match answer {
+            Some(Answer::String(input)) if matches!(self.kind, InteractionKind::Password) => {
+                // the secret itself is only available through the varbag
+                self.update_varbag(input, varbag);
+                Response::Text(REDACTED.to_string())
+            }
             Some(Answer::String(input)) => {
-                self.update_varbag(&input, varbag);
+                self.update_varbag(input, varbag);
 
                 Response::Text(input.to_string())
             }
@@ -200,10 +932,20 @@ impl Interaction {
                 self.update_varbag(&selected.text, varbag);
                 Response::Text(selected.text.clone())
             }
-            Some(Answer::Bool(confirmed)) if *confirmed => {
-                let as_string = "true".to_string();
-                self.update_varbag(&as_string, varbag);
-                Response::Text(as_string)
+            Some(Answer::ListItems(selected)) => {
+                let items = selected
+                    .iter()
+                    .map(|item| item.text.clone())
+                    .collect::<Vec<_>>();
+                self.update_varbag(
+                    &items.join(self.separator.as_deref().unwrap_or(",")),
+                    varbag,
+                );
+                Response::List(items)
+            }
+            Some(Answer::Bool(confirmed)) => {
+                self.update_varbag(&confirmed.to_string(), varbag);
+                Response::Bool(*confirmed)
             }
             None => {
                 Response::Cancel
@@ -213,21 +955,99 @@ impl Interaction {
                 Response::Cancel
                 // not supported question types
             }
+        }
+    }
+
+    /// Convert a given value into an answer, checking it fits the kind of interaction:
+    /// a string for input, a boolean for confirm, one of the options for select, and a
+    /// list of options (or a string joined with the separator) for multiselect
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the value does not fit the interaction
+    pub fn answer_from(&self, value: &serde_json::Value) -> Result<Answer> {
+        let options = self.options.as_deref().unwrap_or_default();
+        let option = |text: &str| {
+            options
+                .iter()
+                .position(|option| option == text)
+                .map(|index| requestty::ListItem {
+                    index,
+                    text: text.to_string(),
+                })
+                .ok_or_else(|| {
+                    anyhow::anyhow!("'{}' is not one of the options {:?}", text, options)
+                })
+        };
+        Ok(match (&self.kind, value) {
+            (InteractionKind::Input | InteractionKind::Password, serde_json::Value::String(s)) => {
+                Answer::String(s.clone())
+            }
+            (
+                InteractionKind::Input | InteractionKind::Password,
+                serde_json::Value::Number(_) | serde_json::Value::Bool(_),
+            ) => Answer::String(value.to_string()),
+            (InteractionKind::Confirm, serde_json::Value::Bool(b)) => Answer::Bool(*b),
+            (InteractionKind::Confirm, serde_json::Value::String(s)) => {
+                Answer::Bool(match s.to_lowercase().as_str() {
+                    "true" | "yes" | "y" => true,
+                    "false" | "no" | "n" => false,
+                    _ => anyhow::bail!("expected a boolean, got '{}'", s),
+                })
+            }
+            (InteractionKind::Select, serde_json::Value::String(s)) => Answer::ListItem(option(s)?),
+            (InteractionKind::MultiSelect, serde_json::Value::Array(items)) => Answer::ListItems(
+                items
+                    .iter()
+                    .map(|item| {
+                        item.as_str()
+                            .ok_or_else(|| anyhow::anyhow!("expected a string, got {}", item))
+                            .and_then(option)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            (InteractionKind::MultiSelect, serde_json::Value::String(s)) => Answer::ListItems(
+                s.split(self.separator.as_deref().unwrap_or(","))
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(option)
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            (kind, value) => anyhow::bail!("{} is not a valid answer for {:?}", value, kind),
         })
     }
 
-    fn to_default_answer(&self) -> Option<Answer> {
-        if let Some(default) = &self.default_value {
-            Some(match default {
-                DefaultValue::Input(ref input) => Answer::String(input.clone()),
-                DefaultValue::Select(index) => Answer::ListItem(requestty::ListItem {
-                    text: self.options.as_ref().unwrap()[*index].clone(),
-                    index: *index,
-                }),
-                DefaultValue::Confirm(confirmed) => Answer::Bool(*confirmed),
+    /// The answer given by the default value, if there is one. Option indices out
+    /// of range are not a valid default, see [`validate`](crate::validate::validate)
+    pub fn to_default_answer(&self) -> Option<Answer> {
+        let option = |index: usize| {
+            self.options
+                .as_ref()
+                .and_then(|options| options.get(index))
+                .map(|text| requestty::ListItem {
+                    text: text.clone(),
+                    index,
+                })
+        };
+        self.default_value
+            .as_ref()
+            .and_then(|default| match default {
+                DefaultValue::Input(ref input) => Some(Answer::String(input.clone())),
+                DefaultValue::Select(index) => option(*index).map(Answer::ListItem),
+                DefaultValue::Confirm(confirmed) => Some(Answer::Bool(*confirmed)),
+                DefaultValue::MultiSelect(indices) => indices
+                    .iter()
+                    .map(|index| option(*index))
+                    .collect::<Option<Vec<_>>>()
+                    .map(Answer::ListItems),
             })
-        } else {
-            None
+    }
+
+    /// an answer standing in for a missing one in dry runs: `<out>`, or yes for a confirm
+    pub fn to_placeholder_answer(&self) -> Answer {
+        match self.kind {
+            InteractionKind::Confirm => Answer::Bool(true),
+            _ => Answer::String(format!("<{}>", self.out.as_deref().unwrap_or("answer"))),
         }
     }
 
@@ -262,6 +1082,47 @@ impl Interaction {
                 }
                 .build()
             }
+            InteractionKind::MultiSelect => {
+                let selected = match self.default_value {
+                    Some(DefaultValue::MultiSelect(ref indices)) => indices.clone(),
+                    _ => vec![],
+                };
+                let builder = Question::multi_select("question")
+                    .message(self.prompt.clone())
+                    .choices_with_default(
+                        self.options
+                            .clone()
+                            .unwrap_or_default()
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, option)| (option, selected.contains(&i))),
+                    );
+                if let Some(ask) = self.ask_if_has_default {
+                    if ask {
+                        builder.ask_if_answered(ask)
+                    } else {
+                        builder
+                    }
+                } else {
+                    builder
+                }
+                .build()
+            }
+            InteractionKind::Password => {
+                let builder = Question::password("question")
+                    .message(self.prompt.clone())
+                    .mask('*');
+                if let Some(ask) = self.ask_if_has_default {
+                    if ask {
+                        builder.ask_if_answered(ask)
+                    } else {
+                        builder
+                    }
+                } else {
+                    builder
+                }
+                .build()
+            }
             InteractionKind::Confirm => {
                 let builder = Question::confirm("question").message(self.prompt.clone());
                 if let Some(ask) = self.ask_if_has_default {
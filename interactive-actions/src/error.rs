@@ -0,0 +1,166 @@
+//!
+//! Errors of running actions
+//!
+use crate::data::ActionResult;
+use thiserror::Error;
+
+///
+/// Why an action stopped the run
+///
+#[derive(Debug, Error)]
+pub enum ActionError {
+    /// the user cancelled a prompt, and the action has `break_if_cancel`
+    #[error("in action '{action}': stop requested (break_if_cancel)")]
+    Cancelled {
+        /// name of the action
+        action: String,
+    },
+
+    /// the user declined a confirm, and the action's decline policy breaks
+    #[error("in action '{action}': stop requested (declined)")]
+    Declined {
+        /// name of the action
+        action: String,
+    },
+
+    /// the run script returned a non-zero exit code, and the action does not have `ignore_exit`
+    #[error("in action '{action}': command returned exit code '{code}'")]
+    ScriptFailed {
+        /// name of the action
+        action: String,
+        /// exit code of the script
+        code: i32,
+        /// stderr of the script, empty unless the output is captured
+        stderr: String,
+    },
+
+    /// the run script ran longer than the action's `timeout`, and was killed
+    #[error("in action '{action}': timed out after {timeout}s")]
+    TimedOut {
+        /// name of the action
+        action: String,
+        /// the timeout, in seconds
+        timeout: f64,
+    },
+
+    /// the run script was killed through the runner's [`CancelHandle`](crate::exec::CancelHandle)
+    #[error("in action '{action}': aborted")]
+    Aborted {
+        /// name of the action
+        action: String,
+    },
+
+    /// the user pressed Ctrl-C during a prompt or a script
+    #[error("in action '{action}': interrupted")]
+    Interrupted {
+        /// name of the action
+        action: String,
+    },
+
+    /// the run script could not be started
+    #[error("in action '{action}': cannot run script: {message}")]
+    Script {
+        /// name of the action
+        action: String,
+        /// what went wrong
+        message: String,
+    },
+
+    /// prompting the user failed, e.g. there is no terminal
+    #[error("in action '{action}': prompt failed: {message}")]
+    Prompt {
+        /// name of the action
+        action: String,
+        /// what went wrong
+        message: String,
+    },
+
+    /// an answer given ahead of time does not fit the interaction, or is missing in non-interactive mode
+    #[error("in action '{action}': {message}")]
+    Answer {
+        /// name of the action
+        action: String,
+        /// what went wrong
+        message: String,
+    },
+
+    /// capturing the script output into variables failed
+    #[error("in action '{action}': cannot capture output: {message}")]
+    Output {
+        /// name of the action
+        action: String,
+        /// what went wrong
+        message: String,
+    },
+
+    /// the action completed, but recording a checkpoint of it failed
+    #[error("in action '{action}': cannot save checkpoint: {message}")]
+    Checkpoint {
+        /// name of the action
+        action: String,
+        /// what went wrong
+        message: String,
+    },
+
+    /// the checkpoint being resumed was recorded for different action definitions
+    #[error(
+        "in action '{action}': cannot resume: actions changed since the checkpoint was recorded"
+    )]
+    StaleCheckpoint {
+        /// name of the first action of the run
+        action: String,
+    },
+
+    /// the action is not valid, e.g. a malformed `when` expression or template
+    #[error("in action '{action}': {message}")]
+    InvalidDefinition {
+        /// name of the action
+        action: String,
+        /// what went wrong
+        message: String,
+    },
+}
+
+impl ActionError {
+    /// name of the action which failed
+    pub fn action(&self) -> &str {
+        match self {
+            Self::Cancelled { action }
+            | Self::Declined { action }
+            | Self::Aborted { action }
+            | Self::Interrupted { action }
+            | Self::StaleCheckpoint { action }
+            | Self::TimedOut { action, .. }
+            | Self::ScriptFailed { action, .. }
+            | Self::Script { action, .. }
+            | Self::Prompt { action, .. }
+            | Self::Answer { action, .. }
+            | Self::Output { action, .. }
+            | Self::Checkpoint { action, .. }
+            | Self::InvalidDefinition { action, .. } => action,
+        }
+    }
+
+    /// is this a stop requested by the user or the embedding application, rather than a failure
+    pub fn is_cancel(&self) -> bool {
+        matches!(
+            self,
+            Self::Cancelled { .. }
+                | Self::Declined { .. }
+                | Self::Aborted { .. }
+                | Self::Interrupted { .. }
+        )
+    }
+}
+
+///
+/// An [`ActionError`], along with the results of the actions which completed before it
+///
+#[derive(Debug, Error)]
+#[error("{error}")]
+pub struct RunError {
+    /// why the run stopped
+    pub error: ActionError,
+    /// results of the actions which completed before the error
+    pub results: Vec<ActionResult>,
+}
@@ -0,0 +1,731 @@
+//!
+//! Running scripts in a child shell, with a timeout and cancellation
+//!
+use crate::data::Capture;
+use crate::observer::Stream;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::OnceLock;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const POLL: Duration = Duration::from_millis(10);
+
+/// how long an interrupted script has to exit, before it is killed
+const GRACE: Duration = Duration::from_secs(2);
+
+///
+/// Aborts the script currently run by an [`ActionRunner`](crate::ActionRunner), from any thread
+///
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    /// abort the script currently running, or the next one to run
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// was a cancel requested, and not yet acted on
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// act on a cancel request: returns whether there was one, and clears it
+    pub fn take(&self) -> bool {
+        self.cancelled.swap(false, Ordering::SeqCst)
+    }
+
+    /// interrupt the script currently running, or the next one to run, as if the user
+    /// pressed Ctrl-C: the script gets a SIGINT, and is killed if it does not exit soon after
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    /// act on an interrupt: returns whether there was one, and clears it
+    pub fn take_interrupt(&self) -> bool {
+        self.interrupted.swap(false, Ordering::SeqCst)
+    }
+
+    /// Turn SIGINT into [`CancelHandle::interrupt`] until the guard is dropped.
+    /// A second SIGINT while the first is handled terminates the process, as usual.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the signal handler cannot be installed
+    #[cfg(unix)]
+    pub fn trap_interrupt(&self) -> std::io::Result<InterruptGuard> {
+        let terminate = terminate_on_interrupt()?;
+        terminate.store(false, Ordering::SeqCst);
+        let id = signal_hook::flag::register(libc::SIGINT, self.interrupted.clone())?;
+        Ok(InterruptGuard { id })
+    }
+
+    /// Turn SIGINT into [`CancelHandle::interrupt`] until the guard is dropped.
+    /// Does nothing on this platform.
+    ///
+    /// # Errors
+    ///
+    /// This function never returns an error on this platform
+    #[cfg(not(unix))]
+    pub fn trap_interrupt(&self) -> std::io::Result<InterruptGuard> {
+        Ok(InterruptGuard {})
+    }
+}
+
+/// flag which makes SIGINT terminate the process, set whenever no interrupt is trapped.
+/// installed once: signal handlers cannot be uninstalled without losing the default action
+#[cfg(unix)]
+fn terminate_on_interrupt() -> std::io::Result<&'static Arc<AtomicBool>> {
+    static TERMINATE: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    if let Some(terminate) = TERMINATE.get() {
+        return Ok(terminate);
+    }
+    let terminate = Arc::new(AtomicBool::new(true));
+    // handlers run in order: terminate if set, then set it so the next SIGINT terminates
+    signal_hook::flag::register_conditional_default(libc::SIGINT, terminate.clone())?;
+    signal_hook::flag::register(libc::SIGINT, terminate.clone())?;
+    Ok(TERMINATE.get_or_init(|| terminate))
+}
+
+///
+/// Traps SIGINT while alive, see [`CancelHandle::trap_interrupt`]
+///
+#[derive(Debug)]
+pub struct InterruptGuard {
+    #[cfg(unix)]
+    id: signal_hook::SigId,
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            signal_hook::low_level::unregister(self.id);
+            if let Ok(terminate) = terminate_on_interrupt() {
+                terminate.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+///
+/// How to run a script
+///
+#[derive(Clone, Default)]
+pub struct ScriptOptions {
+    /// shell running the script: a program and its arguments, split on whitespace, with the
+    /// script appended, e.g. `bash -eu -c`. `sh -c` by default, and `cmd /C` on windows
+    pub shell: Option<String>,
+    /// working directory of the script, the current one if not set
+    pub working_dir: Option<PathBuf>,
+    /// environment variables on top of the inherited ones
+    pub env: HashMap<String, String>,
+    /// capture stdout and stderr, otherwise they go to the terminal
+    pub capture: Capture,
+    /// prefix of every line streamed to the terminal in [`Capture::Tee`] mode
+    pub prefix: String,
+    /// most bytes of each stream to keep when capturing, dropping the start of the output beyond it
+    pub max_capture: Option<usize>,
+    /// have the shell echo every command to stderr. only the default shell does this
+    pub print_commands: bool,
+    /// kill the script once this much time passed, along with whatever it left running
+    /// in the background which still holds its output open
+    pub timeout: Option<Duration>,
+    /// called with every line of output, without its line ending. the output is then
+    /// piped rather than inherited, even when it is not captured
+    pub on_line: Option<LineFn>,
+}
+
+/// receives lines of script output, see [`ScriptOptions::on_line`]
+pub type LineFn = Arc<dyn Fn(Stream, &str) + Send + Sync>;
+
+impl fmt::Debug for ScriptOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptOptions")
+            .field("shell", &self.shell)
+            .field("working_dir", &self.working_dir)
+            .field("env", &self.env)
+            .field("capture", &self.capture)
+            .field("prefix", &self.prefix)
+            .field("max_capture", &self.max_capture)
+            .field("print_commands", &self.print_commands)
+            .field("timeout", &self.timeout)
+            .field("on_line", &self.on_line.is_some())
+            .finish()
+    }
+}
+
+///
+/// How a script ended
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// the script exited with a code. a script killed by a signal exits with `128 + signal`
+    Code(i32),
+    /// the script was killed because it ran out of time
+    TimedOut,
+    /// the script was killed because of a [`CancelHandle::cancel`]
+    Cancelled,
+    /// the script was sent a SIGINT because of a [`CancelHandle::interrupt`]
+    Interrupted,
+}
+
+///
+/// Outcome of running a script
+///
+#[derive(Clone, Debug)]
+pub struct Output {
+    /// how the script ended
+    pub exit: Exit,
+    /// captured stdout, empty unless captured
+    pub out: String,
+    /// captured stderr, empty unless captured
+    pub err: String,
+}
+
+fn command(script: &str, options: &ScriptOptions) -> Command {
+    let mut cmd = match options.shell {
+        Some(ref shell) => {
+            let mut words = shell.split_whitespace();
+            let mut cmd = Command::new(words.next().unwrap_or_default());
+            cmd.args(words).arg(script);
+            cmd
+        }
+        #[cfg(unix)]
+        None => {
+            let mut cmd = Command::new("sh");
+            if options.print_commands {
+                cmd.arg("-x");
+            }
+            cmd.arg("-c").arg(script);
+            cmd
+        }
+        #[cfg(not(unix))]
+        None => {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C").arg(script);
+            cmd
+        }
+    };
+    // its own process group, so everything it started is killed or interrupted with it
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    if let Some(ref dir) = options.working_dir {
+        cmd.current_dir(dir);
+    }
+    cmd.envs(&options.env);
+    if options.capture.is_captured() || options.on_line.is_some() {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    cmd
+}
+
+/// Captured output, keeping at most `max` bytes: the end of the output, after a truncation marker
+struct Captured {
+    text: String,
+    dropped: usize,
+    max: Option<usize>,
+}
+
+impl Captured {
+    fn new(max: Option<usize>) -> Self {
+        Self {
+            text: String::new(),
+            dropped: 0,
+            max,
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        self.text.push_str(line);
+        let Some(max) = self.max else {
+            return;
+        };
+        if self.text.len() > max {
+            let mut start = self.text.len() - max;
+            while !self.text.is_char_boundary(start) {
+                start += 1;
+            }
+            // drop whole lines where possible, unless that drops everything
+            let cut = match self.text[start..].find('\n') {
+                _ if self.text[..start].ends_with('\n') => start,
+                Some(i) if start + i + 1 < self.text.len() => start + i + 1,
+                _ => start,
+            };
+            self.text.drain(..cut);
+            self.dropped += cut;
+        }
+    }
+
+    fn finish(self) -> String {
+        if self.dropped == 0 {
+            self.text
+        } else {
+            format!("[... {} bytes truncated ...]\n{}", self.dropped, self.text)
+        }
+    }
+}
+
+/// read a stream line by line, until it closes. in tee mode, also copy every line to `tee`
+fn reader<R, W>(
+    stream: Option<R>,
+    kind: Stream,
+    tee: W,
+    options: &ScriptOptions,
+) -> Option<JoinHandle<String>>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let tee = (options.capture == Capture::Tee).then_some(tee);
+    let prefix = options.prefix.clone();
+    let on_line = options.on_line.clone();
+    let mut captured = options
+        .capture
+        .is_captured()
+        .then(|| Captured::new(options.max_capture));
+    stream.map(|stream| {
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+            let mut tee = tee;
+            let mut line = vec![];
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                let text = String::from_utf8_lossy(&line);
+                if let Some(ref mut tee) = tee {
+                    let _ = write!(tee, "{prefix}{text}").and_then(|()| tee.flush());
+                }
+                if let Some(ref on_line) = on_line {
+                    on_line(kind, text.trim_end_matches(['\n', '\r']));
+                }
+                if let Some(ref mut captured) = captured {
+                    captured.push(&text);
+                }
+                line.clear();
+            }
+            captured.map(Captured::finish).unwrap_or_default()
+        })
+    })
+}
+
+/// send a signal to the whole group of the child
+#[cfg(unix)]
+fn signal(child: &Child, signal: libc::c_int) {
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: plain syscall. the group is the child's own, since it was spawned with process_group(0)
+        unsafe {
+            libc::kill(-pid, signal);
+        }
+    }
+}
+
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    signal(child, libc::SIGKILL);
+    let _ = child.kill();
+}
+
+/// forward an interrupt to the child, and give it some time to exit
+fn interrupt(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        signal(child, libc::SIGINT);
+        let deadline = Instant::now() + GRACE;
+        while Instant::now() < deadline {
+            if matches!(child.try_wait(), Ok(Some(_)) | Err(_)) {
+                return;
+            }
+            std::thread::sleep(POLL);
+        }
+    }
+    kill(child);
+}
+
+/// Hands the terminal to the process group of a script, like a shell does with its jobs,
+/// so the script can read from it and gets Ctrl-C from it, and takes it back once dropped.
+/// Does nothing when stdin is not a terminal, or this process does not own it.
+#[cfg(unix)]
+#[derive(Debug)]
+struct Foreground {
+    owner: Option<libc::pid_t>,
+}
+
+#[cfg(unix)]
+impl Foreground {
+    fn hand_to(child: &Child) -> Self {
+        let Ok(pid) = libc::pid_t::try_from(child.id()) else {
+            return Self { owner: None };
+        };
+        // SAFETY: plain syscalls on stdin
+        let owner = unsafe {
+            let owner = libc::getpgrp();
+            (libc::isatty(libc::STDIN_FILENO) == 1
+                && libc::tcgetpgrp(libc::STDIN_FILENO) == owner
+                && set_foreground(pid))
+            .then_some(owner)
+        };
+        Self { owner }
+    }
+
+    fn is_held(&self) -> bool {
+        self.owner.is_some()
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        if let Some(owner) = self.owner {
+            set_foreground(owner);
+        }
+    }
+}
+
+/// make a process group the foreground one of the terminal on stdin. SIGTTOU is blocked
+/// meanwhile, since a background process taking the terminal back would be stopped by it
+#[cfg(unix)]
+fn set_foreground(group: libc::pid_t) -> bool {
+    // SAFETY: plain syscalls, on signal sets which are initialized before use
+    unsafe {
+        let mut block = std::mem::zeroed::<libc::sigset_t>();
+        let mut old = std::mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut block);
+        libc::sigaddset(&mut block, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &block, &mut old);
+        let done = libc::tcsetpgrp(libc::STDIN_FILENO, group) == 0;
+        libc::pthread_sigmask(libc::SIG_SETMASK, &old, std::ptr::null_mut());
+        done
+    }
+}
+
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(-1)
+}
+
+///
+/// A script which was spawned, see [`spawn`]
+///
+#[derive(Debug)]
+pub struct Running {
+    child: Child,
+    out: Option<JoinHandle<String>>,
+    err: Option<JoinHandle<String>>,
+    timeout: Option<Duration>,
+    started: Instant,
+    #[cfg(unix)]
+    foreground: Foreground,
+}
+
+/// Spawn a script with `sh -c` (`cmd /C` on Windows)
+///
+/// # Errors
+///
+/// This function will return an error if the script cannot be started
+pub fn spawn(script: &str, options: &ScriptOptions) -> std::io::Result<Running> {
+    let mut child = command(script, options).spawn()?;
+    #[cfg(unix)]
+    let foreground = Foreground::hand_to(&child);
+    let out = reader(
+        child.stdout.take(),
+        Stream::Stdout,
+        std::io::stdout(),
+        options,
+    );
+    let err = reader(
+        child.stderr.take(),
+        Stream::Stderr,
+        std::io::stderr(),
+        options,
+    );
+    Ok(Running {
+        child,
+        out,
+        err,
+        timeout: options.timeout,
+        started: Instant::now(),
+        #[cfg(unix)]
+        foreground,
+    })
+}
+
+impl Running {
+    /// process id of the shell running the script
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Wait for the script to end, killing it on timeout or cancel, and forwarding interrupts to it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if waiting on the script fails
+    pub fn wait(mut self, cancel: &CancelHandle) -> std::io::Result<Output> {
+        // holding the terminal, the script gets Ctrl-C rather than this process,
+        // so dying of SIGINT is an interrupt
+        #[cfg(unix)]
+        let interrupted = |status: ExitStatus| {
+            use std::os::unix::process::ExitStatusExt;
+            self.foreground.is_held() && status.signal() == Some(libc::SIGINT)
+        };
+        #[cfg(not(unix))]
+        let interrupted = |_: ExitStatus| false;
+        let child = &mut self.child;
+        let deadline = self.timeout.map(|timeout| self.started + timeout);
+        let mut exit = loop {
+            if let Some(status) = child.try_wait()? {
+                break if interrupted(status) {
+                    Exit::Interrupted
+                } else {
+                    Exit::Code(exit_code(status))
+                };
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                kill(child);
+                break Exit::TimedOut;
+            }
+            if cancel.take() {
+                kill(child);
+                break Exit::Cancelled;
+            }
+            if cancel.take_interrupt() {
+                interrupt(child);
+                break Exit::Interrupted;
+            }
+            std::thread::sleep(POLL);
+        };
+        let _ = child.wait();
+        #[cfg(unix)]
+        drop(self.foreground);
+
+        // what the script left running in the background can hold its output open, so the
+        // output is waited for under the same deadline and cancel, and the group killed then
+        let open = |reader: &Option<JoinHandle<String>>| {
+            reader.as_ref().is_some_and(|reader| !reader.is_finished())
+        };
+        while open(&self.out) || open(&self.err) {
+            if !matches!(exit, Exit::Code(_)) {
+                kill(child);
+                break;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                kill(child);
+                exit = Exit::TimedOut;
+                break;
+            }
+            if cancel.take() {
+                kill(child);
+                exit = Exit::Cancelled;
+                break;
+            }
+            std::thread::sleep(POLL);
+        }
+
+        let collect = |reader: Option<JoinHandle<String>>| {
+            reader
+                .and_then(|reader| reader.join().ok())
+                .unwrap_or_default()
+        };
+        Ok(Output {
+            exit,
+            out: collect(self.out),
+            err: collect(self.err),
+        })
+    }
+}
+
+/// Run a script with `sh -c` (`cmd /C` on Windows) and wait for it to end,
+/// killing it on timeout or cancel, and forwarding interrupts to it
+///
+/// # Errors
+///
+/// This function will return an error if the script cannot be started
+pub fn run(
+    script: &str,
+    options: &ScriptOptions,
+    cancel: &CancelHandle,
+) -> std::io::Result<Output> {
+    spawn(script, options)?.wait(cancel)
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_run() {
+        let options = ScriptOptions {
+            capture: Capture::On,
+            env: HashMap::from([("IA_TEST_EXEC".to_string(), "tlv".to_string())]),
+            ..ScriptOptions::default()
+        };
+        let output = run(
+            "echo $IA_TEST_EXEC; echo oops >&2; exit 3",
+            &options,
+            &CancelHandle::default(),
+        )
+        .unwrap();
+        assert_eq!(output.exit, Exit::Code(3));
+        assert_eq!(output.out, "tlv\n");
+        assert_eq!(output.err, "oops\n");
+    }
+
+    #[test]
+    fn test_shell() {
+        let options = ScriptOptions {
+            shell: Some("sh -e -c".to_string()),
+            capture: Capture::On,
+            ..ScriptOptions::default()
+        };
+        let output = run("false; echo after", &options, &CancelHandle::default()).unwrap();
+        assert_eq!(output.exit, Exit::Code(1));
+        assert_eq!(output.out, "");
+    }
+
+    #[test]
+    fn test_timeout() {
+        let options = ScriptOptions {
+            capture: Capture::On,
+            timeout: Some(Duration::from_millis(100)),
+            ..ScriptOptions::default()
+        };
+        let started = Instant::now();
+        // the grandchild sleep holds the pipes open, so this only returns if the whole group is killed
+        let output = run("echo started; sleep 10", &options, &CancelHandle::default()).unwrap();
+        assert_eq!(output.exit, Exit::TimedOut);
+        assert_eq!(output.out, "started\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let cancel = CancelHandle::default();
+        let handle = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.cancel();
+        });
+        let output = run("exec sleep 10", &ScriptOptions::default(), &cancel).unwrap();
+        assert_eq!(output.exit, Exit::Cancelled);
+        assert!(!cancel.is_cancelled());
+
+        // the grandchild sleep holds the pipes open, so this only returns if the whole group is killed
+        let handle = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            handle.cancel();
+        });
+        let options = ScriptOptions {
+            capture: Capture::On,
+            ..ScriptOptions::default()
+        };
+        let started = Instant::now();
+        let output = run("sleep 3; echo after", &options, &cancel).unwrap();
+        assert_eq!(output.exit, Exit::Cancelled);
+        assert_eq!(output.out, "");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_background_timeout() {
+        // the backgrounded sleep keeps the output open after the shell exits
+        let options = ScriptOptions {
+            capture: Capture::On,
+            timeout: Some(Duration::from_millis(500)),
+            ..ScriptOptions::default()
+        };
+        let started = Instant::now();
+        let output = run("sleep 100 & echo hi", &options, &CancelHandle::default()).unwrap();
+        assert_eq!(output.exit, Exit::TimedOut);
+        assert_eq!(output.out, "hi\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let cancel = CancelHandle::default();
+        let handle = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.interrupt();
+        });
+        let options = ScriptOptions {
+            capture: Capture::On,
+            ..ScriptOptions::default()
+        };
+        let started = Instant::now();
+        let output = run(
+            "trap 'kill $!; echo interrupted; exit 130' INT; sleep 10 & wait",
+            &options,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(output.exit, Exit::Interrupted);
+        assert_eq!(output.out, "interrupted\n");
+        assert!(started.elapsed() < GRACE);
+    }
+
+    #[test]
+    fn test_max_capture() {
+        let options = ScriptOptions {
+            capture: Capture::Tee,
+            prefix: "[test] ".to_string(),
+            max_capture: Some(12),
+            ..ScriptOptions::default()
+        };
+        let output = run(
+            "for i in 1 2 3 4 5; do echo line $i; done",
+            &options,
+            &CancelHandle::default(),
+        )
+        .unwrap();
+        assert_eq!(output.out, "[... 28 bytes truncated ...]\nline 5\n");
+
+        // a cut never splits a character
+        let mut captured = Captured::new(Some(3));
+        captured.push("abcé");
+        captured.push("é");
+        assert_eq!(captured.finish(), "[... 5 bytes truncated ...]\né");
+    }
+
+    #[test]
+    fn test_on_line() {
+        let lines = Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = lines.clone();
+        let options = ScriptOptions {
+            on_line: Some(Arc::new(move |stream, line: &str| {
+                sink.lock().unwrap().push((stream, line.to_string()));
+            })),
+            ..ScriptOptions::default()
+        };
+        let output = run("echo a; echo b", &options, &CancelHandle::default()).unwrap();
+        assert_eq!(output.out, "");
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                (Stream::Stdout, "a".to_string()),
+                (Stream::Stdout, "b".to_string())
+            ]
+        );
+    }
+}
@@ -8,6 +8,10 @@
 //!
 //! You also have additional control flags such as `ignore_exit`, `capture` and others. See below:
 //!
+//! ## Templates
+//! Run scripts, prompts, options and input defaults are templates (see [`template`]), rendered
+//! with the variables captured so far, e.g. `run: echo {{ city | upper }}`.
+//!
 //!
 //! ## Examples
 //! Run a script conditionally, only after confirming:
@@ -79,14 +83,37 @@
 #![allow(clippy::use_self)]
 #![allow(clippy::missing_const_for_fn)]
 
+pub mod answers;
+pub mod checkpoint;
+pub mod condition;
 pub mod data;
+pub mod error;
+pub mod exec;
+pub mod observer;
+pub mod report;
+pub mod template;
+pub mod validate;
+pub mod workflow;
 
-use anyhow::{Error, Result};
-use data::{Action, ActionHook, ActionResult, Response, RunResult, VarBag};
+use answers::AnswerSource;
+use checkpoint::{Checkpoint, CheckpointStore};
+use condition::Condition;
+use data::{
+    Action, ActionHook, ActionResult, ActionStatus, Capture, DeclinePolicy, EnvExport, Interaction,
+    InteractionKind, Response, RunResult, Stopwatch, VarBag,
+};
+use error::{ActionError, RunError};
+use exec::CancelHandle;
+use observer::{OutputObserver, RunObserver, Stream};
+use report::RunReport;
 use requestty_ui::events::{KeyEvent, TestEvents};
-use run_script::IoOptions;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::vec::IntoIter;
+use template::Renderer;
+use workflow::Workflow;
 
 ///
 /// Runs [`Action`]s and keeps track of variables in `varbag`.
@@ -95,6 +122,65 @@ use std::vec::IntoIter;
 pub struct ActionRunner {
     /// synthetic events to be injected to prompts, useful in tests
     pub events: Option<TestEvents<IntoIter<KeyEvent>>>,
+
+    /// render a variable which is not set as an empty string, instead of failing. risky
+    /// in run scripts: `rm -rf /{{dir}}` would run as `rm -rf /`
+    pub lenient_templates: bool,
+
+    /// export variables to the environment of every run script, unless the action says otherwise
+    pub export_env: Option<EnvExport>,
+
+    /// shell-quote every `{{var}}` in run scripts, unless the action says otherwise
+    pub shell_escape: bool,
+
+    /// run scripts as they are, without templating, unless the action says otherwise
+    pub raw_scripts: bool,
+
+    /// answers given ahead of time, consulted in order before prompting. An interaction
+    /// with an answer is not prompted, like when it has a default and `ask_if_has_default: false`
+    pub answers: Vec<Box<dyn AnswerSource>>,
+
+    /// never prompt: interactions without a given answer take their default, or fail
+    pub non_interactive: bool,
+
+    /// resolve interactions and render run scripts, but do not execute them.
+    /// results are marked [`ActionStatus::WouldRun`] and carry the rendered script.
+    /// nothing is prompted: interactions without a given answer take their default,
+    /// or a placeholder such as `<city>` (yes for a confirm)
+    pub dry_run: bool,
+
+    /// record a [`Checkpoint`] after every completed action, so a failed run can be resumed.
+    /// the checkpoint is removed once all actions complete
+    pub checkpoint: Option<CheckpointStore>,
+
+    /// a checkpoint of a previous run: its variables are restored, and the actions it
+    /// completed are not run again, though secrets they set are asked for again.
+    /// a stale checkpoint stops the run with [`ActionError::StaleCheckpoint`]
+    pub resume: Option<Checkpoint>,
+
+    /// actions to run when the run stops on a failure or a cancel, see [`Action::on_failure`]
+    pub on_failure: Vec<Action>,
+
+    /// actions to run at the end of every run, whether it failed or not
+    pub finally: Vec<Action>,
+
+    /// abort the running script from another thread: clone it before running,
+    /// and call [`CancelHandle::cancel`]. the run then stops with [`ActionError::Aborted`]
+    pub cancel: CancelHandle,
+
+    /// on Ctrl-C, forward the interrupt to the running script and stop with
+    /// [`ActionError::Interrupted`], running the failure handlers, instead of the process dying
+    pub handle_interrupt: bool,
+
+    /// most bytes of each of stdout and stderr to keep when capturing, unless the action says otherwise
+    pub max_capture: Option<usize>,
+
+    /// receives every line of script output as it arrives, instead of the terminal
+    /// (unless the action tees it), along with the echo of the script commands
+    pub output: Option<Arc<dyn OutputObserver>>,
+
+    /// receives the events of every run: actions starting, scripts finishing, failures and so on
+    pub observer: Option<Box<dyn RunObserver>>,
 }
 
 impl ActionRunner {
@@ -103,15 +189,17 @@ impl ActionRunner {
     pub fn with_events(events: Vec<KeyEvent>) -> Self {
         Self {
             events: Some(TestEvents::new(events)),
+            ..Self::default()
         }
     }
 
-    /// Runs actions
+    /// Runs actions. `progress` is called as every action starts, like
+    /// [`RunObserver::action_start`] of the [`observer`](ActionRunner::observer)
     ///
     /// # Errors
     ///
-    /// This function will return an error when actions fail
-    #[allow(clippy::needless_pass_by_value)]
+    /// This function will return an error when actions fail, along with the results
+    /// of the actions which completed before the failure
     pub fn run<P>(
         &mut self,
         actions: &[Action],
@@ -119,94 +207,838 @@ impl ActionRunner {
         varbag: &mut VarBag,
         hook: ActionHook,
         progress: Option<P>,
-    ) -> Result<Vec<ActionResult>>
+    ) -> Result<Vec<ActionResult>, RunError>
     where
         P: Fn(&Action),
     {
-        actions
-            .iter()
-            .filter(|action| action.hook == hook)
-            .map(|action| {
-                // get interactive response from the user if any is defined
-                if let Some(ref progress) = progress {
-                    progress(action);
+        self.run_report(actions, working_dir, varbag, hook, progress)
+            .into_result()
+    }
+
+    /// Runs actions, and reports the results of the completed actions, the error
+    /// which stopped the run if any, and the actions which never ran
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn run_report<P>(
+        &mut self,
+        actions: &[Action],
+        working_dir: Option<&Path>,
+        varbag: &mut VarBag,
+        hook: ActionHook,
+        progress: Option<P>,
+    ) -> RunReport
+    where
+        P: Fn(&Action),
+    {
+        let renderer = Renderer::new(!self.lenient_templates);
+        let mut report = RunReport {
+            results: vec![],
+            error: None,
+            failed: None,
+            pending: vec![],
+            handlers: vec![],
+            handler_errors: vec![],
+        };
+        let _interrupt = if self.handle_interrupt {
+            match self.cancel.trap_interrupt() {
+                Ok(guard) => Some(guard),
+                Err(e) => {
+                    self.notify(|o| o.warning(&format!("cannot handle interrupts: {e}")));
+                    None
                 }
+            }
+        } else {
+            None
+        };
+        self.notify(|o| {
+            o.workflow_start(
+                &actions
+                    .iter()
+                    .filter(|action| action.hook == hook)
+                    .collect::<Vec<_>>(),
+            );
+        });
+        let mut checkpoint = match self.resume.take() {
+            Some(resume) if resume.is_stale(actions, &hook) => {
+                report.pending = actions
+                    .iter()
+                    .filter(|action| action.hook == hook)
+                    .map(|action| action.name.clone())
+                    .collect();
+                report.error = Some(ActionError::StaleCheckpoint {
+                    action: report.pending.first().cloned().unwrap_or_default(),
+                });
+                self.notify(|o| o.workflow_end(&report));
+                return report;
+            }
+            Some(resume) => {
+                for (k, v) in &resume.varbag {
+                    varbag.insert(k.clone(), v.clone());
+                }
+                resume
+            }
+            None => Checkpoint::new(actions, &hook),
+        };
+        for action in actions.iter().filter(|action| action.hook == hook) {
+            if report.error.is_some() {
+                report.pending.push(action.name.clone());
+                continue;
+            }
+            if let Some(i) = checkpoint.completed.iter().position(|n| n == &action.name) {
+                if let Err(error) = self.restore_secret(action, &renderer, varbag) {
+                    report.error = Some(error);
+                    continue;
+                }
+                if let Some(result) = checkpoint.results.get(i) {
+                    report.results.push(result.clone());
+                }
+                self.notify(|o| o.action_skipped(action));
+                continue;
+            }
+            // a cancel or interrupt between scripts stops the run before the next action
+            if self.cancel.take() {
+                report.error = Some(ActionError::Aborted {
+                    action: action.name.clone(),
+                });
+                continue;
+            }
+            if self.cancel.take_interrupt() {
+                report.error = Some(ActionError::Interrupted {
+                    action: action.name.clone(),
+                });
+                continue;
+            }
+            let (result, outcome) = self.run_step(
+                action,
+                &renderer,
+                working_dir,
+                varbag,
+                progress.as_ref(),
+                &mut report,
+            );
+            match outcome {
+                Ok(()) => {
+                    if let Err(error) =
+                        self.save_checkpoint(&mut checkpoint, action, &result, varbag)
+                    {
+                        report.error = Some(error);
+                    }
+                    report.results.push(result);
+                }
+                Err(error) => {
+                    report.error = Some(error);
+                    report.failed = Some(result);
+                }
+            }
+        }
+        if let Some(ref error) = report.error {
+            if !self.on_failure.is_empty() {
+                let shadowed = set_failure_vars(error, varbag);
+                let handlers = self.on_failure.clone();
+                self.run_handlers(
+                    &handlers,
+                    &renderer,
+                    working_dir,
+                    varbag,
+                    progress.as_ref(),
+                    &mut report,
+                );
+                unset_failure_vars(shadowed, varbag);
+            }
+        }
+        let handlers = self.finally.clone();
+        self.run_handlers(
+            &handlers,
+            &renderer,
+            working_dir,
+            varbag,
+            progress.as_ref(),
+            &mut report,
+        );
 
-                let response = action
-                    .interaction
-                    .as_ref()
-                    .map_or(Ok(Response::None), |interaction| {
-                        interaction.play(Some(varbag), self.events.as_mut())
-                    });
+        if report.is_ok() && !self.dry_run {
+            if let Some(ref store) = self.checkpoint {
+                if let Err(e) = store.clear() {
+                    self.notify(|o| o.warning(&format!("{e:#}")));
+                }
+            }
+        }
+        self.notify(|o| o.workflow_end(&report));
+        report
+    }
 
-                // with the defined run script and user response, perform an action
-                response.and_then(|r| match (r, action.run.as_ref()) {
-                    (Response::Cancel, _) => {
-                        if action.break_if_cancel {
-                            Err(anyhow::anyhow!("stop requested (break_if_cancel)"))
-                        } else {
-                            Ok(ActionResult {
-                                name: action.name.clone(),
-                                run: None,
-                                response: Response::Cancel,
-                            })
-                        }
-                    }
-                    (resp, None) => Ok(ActionResult {
-                        name: action.name.clone(),
-                        run: None,
-                        response: resp,
-                    }),
-                    (resp, Some(run)) => {
-                        let mut options = run_script::ScriptOptions::new();
-                        options.working_directory = working_dir.map(std::path::Path::to_path_buf);
-                        options.output_redirection = if action.capture {
-                            IoOptions::Pipe
-                        } else {
-                            IoOptions::Inherit
-                        };
-                        options.print_commands = true;
-                        let args = vec![];
-
-                        // varbag replacements: {{interaction.outvar}} -> value
-                        let script = varbag.iter().fold(run.clone(), |acc, (k, v)| {
-                            acc.replace(&format!("{{{{{k}}}}}"), v)
-                        });
-
-                        run_script::run(script.as_str(), &args, &options)
-                            .map_err(Error::msg)
-                            .and_then(|tup| {
-                                if !action.ignore_exit && tup.0 != 0 {
-                                    anyhow::bail!(
-                                        "in action '{}': command returned exit code '{}'",
-                                        action.name,
-                                        tup.0
-                                    )
-                                }
-                                Ok(tup)
-                            })
-                            .map(|(code, out, err)| ActionResult {
-                                name: action.name.clone(),
-                                run: Some(RunResult {
-                                    script,
-                                    code,
-                                    out,
-                                    err,
-                                }),
-                                response: resp,
-                            })
-                    }
+    /// checkpoints do not store secrets, so ask again for the secret a completed action set
+    fn restore_secret(
+        &mut self,
+        action: &Action,
+        renderer: &Renderer,
+        varbag: &mut VarBag,
+    ) -> Result<(), ActionError> {
+        let Some(ref interaction) = action.interaction else {
+            return Ok(());
+        };
+        let is_missing = interaction
+            .out
+            .as_ref()
+            .is_some_and(|out| !varbag.contains_key(out));
+        if !matches!(interaction.kind, InteractionKind::Password) || !is_missing {
+            return Ok(());
+        }
+        let interaction = renderer.interaction(interaction, varbag).map_err(|e| {
+            ActionError::InvalidDefinition {
+                action: action.name.clone(),
+                message: e.to_string(),
+            }
+        })?;
+        match self.interact(action, &interaction, varbag)? {
+            Response::Cancel => Err(ActionError::Cancelled {
+                action: action.name.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a [`Workflow`]. Its variables are set in `varbag` unless set already, and
+    /// its `on_failure` and `finally` actions which are in `hook` run ahead of the runner's own.
+    /// Follow the actions as they start with [`RunObserver::action_start`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error when actions fail, along with the results
+    /// of the actions which completed before the failure
+    pub fn run_workflow(
+        &mut self,
+        workflow: &Workflow,
+        working_dir: Option<&Path>,
+        varbag: &mut VarBag,
+        hook: ActionHook,
+    ) -> Result<Vec<ActionResult>, RunError> {
+        self.run_workflow_report(workflow, working_dir, varbag, hook)
+            .into_result()
+    }
+
+    /// Runs a [`Workflow`], and reports like [`ActionRunner::run_report`]
+    pub fn run_workflow_report(
+        &mut self,
+        workflow: &Workflow,
+        working_dir: Option<&Path>,
+        varbag: &mut VarBag,
+        hook: ActionHook,
+    ) -> RunReport {
+        workflow.seed(varbag);
+        let in_hook = |actions: Vec<Action>| {
+            actions
+                .into_iter()
+                .filter(|action| action.hook == hook)
+                .collect::<Vec<_>>()
+        };
+        let mut on_failure = in_hook(workflow.on_failure_actions());
+        on_failure.extend(self.on_failure.iter().cloned());
+        let mut finally = in_hook(workflow.finally_actions());
+        finally.extend(self.finally.iter().cloned());
+        let on_failure = std::mem::replace(&mut self.on_failure, on_failure);
+        let finally = std::mem::replace(&mut self.finally, finally);
+
+        let report = self.run_report(
+            &workflow.all_actions(),
+            working_dir,
+            varbag,
+            hook,
+            None::<fn(&Action)>,
+        );
+
+        self.on_failure = on_failure;
+        self.finally = finally;
+        report
+    }
+
+    fn notify<F>(&mut self, event: F)
+    where
+        F: FnOnce(&mut dyn RunObserver),
+    {
+        if let Some(ref mut observer) = self.observer {
+            event(observer.as_mut());
+        }
+    }
+
+    /// run an action, then its `on_failure` handlers if it failed, and its `finally` handlers.
+    /// a failed action still has a result, with status [`ActionStatus::Failed`]
+    fn run_step<P>(
+        &mut self,
+        action: &Action,
+        renderer: &Renderer,
+        working_dir: Option<&Path>,
+        varbag: &mut VarBag,
+        progress: Option<&P>,
+        report: &mut RunReport,
+    ) -> (ActionResult, Result<(), ActionError>)
+    where
+        P: Fn(&Action),
+    {
+        let stopwatch = Stopwatch::start();
+        let mut result = ActionResult::new(&action.name);
+        let outcome = match condition_holds(action, varbag) {
+            // skipped by its `when`, it never starts
+            Ok(false) => {
+                result.status = ActionStatus::Skipped;
+                result.timing = Some(stopwatch.stop());
+                self.notify(|o| o.action_skipped(action));
+                return (result, Ok(()));
+            }
+            Ok(true) => {
+                self.run_action(action, &mut result, renderer, working_dir, varbag, progress)
+            }
+            Err(error) => Err(error),
+        };
+        result.timing = Some(stopwatch.stop());
+        match outcome {
+            Ok(()) if result.status == ActionStatus::Skipped => {
+                self.notify(|o| o.action_skipped(action));
+            }
+            Err(ref error) => {
+                result.status = ActionStatus::Failed;
+                self.notify(|o| o.action_failed(action, error));
+            }
+            Ok(()) => {}
+        }
+        if let Err(ref error) = outcome {
+            if !action.on_failure.is_empty() {
+                let shadowed = set_failure_vars(error, varbag);
+                self.run_handlers(
+                    &action.on_failure,
+                    renderer,
+                    working_dir,
+                    varbag,
+                    progress,
+                    report,
+                );
+                unset_failure_vars(shadowed, varbag);
+            }
+        }
+        self.run_handlers(
+            &action.finally,
+            renderer,
+            working_dir,
+            varbag,
+            progress,
+            report,
+        );
+        (result, outcome)
+    }
+
+    /// run cleanup handlers, all of them even if some fail
+    fn run_handlers<P>(
+        &mut self,
+        handlers: &[Action],
+        renderer: &Renderer,
+        working_dir: Option<&Path>,
+        varbag: &mut VarBag,
+        progress: Option<&P>,
+        report: &mut RunReport,
+    ) where
+        P: Fn(&Action),
+    {
+        for handler in handlers {
+            let (result, outcome) =
+                self.run_step(handler, renderer, working_dir, varbag, progress, report);
+            report.handlers.push(result);
+            if let Err(error) = outcome {
+                report.handler_errors.push(error);
+            }
+        }
+    }
+
+    fn save_checkpoint(
+        &self,
+        checkpoint: &mut Checkpoint,
+        action: &Action,
+        result: &ActionResult,
+        varbag: &VarBag,
+    ) -> Result<(), ActionError> {
+        let Some(ref store) = self.checkpoint else {
+            return Ok(());
+        };
+        if self.dry_run {
+            return Ok(());
+        }
+        checkpoint.completed.push(action.name.clone());
+        checkpoint.results.push(result.clone());
+        checkpoint.varbag = varbag.clone();
+        store.save(checkpoint).map_err(|e| ActionError::Checkpoint {
+            action: action.name.clone(),
+            message: format!("{e:#}"),
+        })
+    }
+
+    /// run an action, filling in `result` as it goes
+    fn run_action<P>(
+        &mut self,
+        action: &Action,
+        result: &mut ActionResult,
+        renderer: &Renderer,
+        working_dir: Option<&Path>,
+        varbag: &mut VarBag,
+        progress: Option<&P>,
+    ) -> Result<(), ActionError>
+    where
+        P: Fn(&Action),
+    {
+        // get interactive response from the user if any is defined
+        if let Some(progress) = progress {
+            progress(action);
+        }
+        self.notify(|o| o.action_start(action));
+
+        if let Some(ref interaction) = action.interaction {
+            let interaction = renderer.interaction(interaction, varbag).map_err(|e| {
+                ActionError::InvalidDefinition {
+                    action: action.name.clone(),
+                    message: e.to_string(),
+                }
+            })?;
+            let stopwatch = Stopwatch::start();
+            let response = self.interact(action, &interaction, varbag);
+            result.interaction_timing = Some(stopwatch.stop());
+            result.response = response?;
+            self.notify(|o| o.interaction_answered(action, &result.response));
+        }
+
+        // with the defined run script and user response, perform an action
+        result.status = self.resolved_status();
+        match result.response {
+            Response::Cancel if action.break_if_cancel => {
+                return Err(ActionError::Cancelled {
+                    action: action.name.clone(),
                 })
-            })
-            .collect::<Result<Vec<_>>>()
+            }
+            Response::Cancel => {
+                result.status = ActionStatus::Cancelled;
+                return Ok(());
+            }
+            Response::Bool(false) if action.on_decline.breaks(action.break_if_cancel) => {
+                return Err(ActionError::Declined {
+                    action: action.name.clone(),
+                })
+            }
+            Response::Bool(false) => {
+                result.status = match action.on_decline {
+                    DeclinePolicy::Skip => ActionStatus::Skipped,
+                    DeclinePolicy::Cancel | DeclinePolicy::Break => ActionStatus::Cancelled,
+                };
+                return Ok(());
+            }
+            _ => {}
+        }
+        let Some(ref run) = action.run else {
+            return Ok(());
+        };
+        let options = self.script_options(action, renderer, working_dir, varbag)?;
+        if self.dry_run {
+            let script = self.render_script(action, run, renderer, varbag)?;
+            result.run = Some(RunResult {
+                script: varbag.redact(&script),
+                working_dir: options.working_dir.map(|dir| dir.display().to_string()),
+                env: redacted_env(&options.env, varbag),
+                ..RunResult::default()
+            });
+            result.status = ActionStatus::WouldRun;
+            return Ok(());
+        }
+        self.run_script(action, run, options, result, renderer, varbag)
+    }
+
+    fn interact(
+        &mut self,
+        action: &Action,
+        interaction: &Interaction,
+        varbag: &mut VarBag,
+    ) -> Result<Response, ActionError> {
+        // answers given ahead of time are looked up by out variable, then by action name
+        let given = self.answers.iter().find_map(|source| {
+            interaction
+                .out
+                .as_ref()
+                .and_then(|out| source.get(out))
+                .or_else(|| source.get(&action.name))
+        });
+        if let Some(value) = given {
+            let answer = interaction
+                .answer_from(&value)
+                .map_err(|e| ActionError::Answer {
+                    action: action.name.clone(),
+                    message: format!("invalid answer: {e}"),
+                })?;
+            return Ok(interaction.respond(Some(&answer), Some(varbag)));
+        }
+
+        if self.non_interactive || self.dry_run {
+            let answer = match interaction.to_default_answer() {
+                Some(answer) => answer,
+                None if self.dry_run => interaction.to_placeholder_answer(),
+                None => {
+                    return Err(ActionError::Answer {
+                        action: action.name.clone(),
+                        message: "no answer was given, and there is no default".to_string(),
+                    })
+                }
+            };
+            return Ok(interaction.respond(Some(&answer), Some(varbag)));
+        }
+
+        interaction
+            .play(Some(varbag), self.events.as_mut())
+            .map_err(|e| prompt_error(action, &e))
+    }
+
+    /// status of an action which is done without running a script
+    fn resolved_status(&self) -> ActionStatus {
+        if self.dry_run {
+            ActionStatus::Planned
+        } else {
+            ActionStatus::Ok
+        }
+    }
+
+    fn render_script(
+        &self,
+        action: &Action,
+        run: &str,
+        renderer: &Renderer,
+        varbag: &VarBag,
+    ) -> Result<String, ActionError> {
+        if action.raw.unwrap_or(self.raw_scripts) {
+            return Ok(run.to_string());
+        }
+        // varbag replacements: {{interaction.outvar}} -> value
+        if action.shell_escape.unwrap_or(self.shell_escape) {
+            renderer.render_shell(run, varbag)
+        } else {
+            renderer.render(run, varbag)
+        }
+        .map_err(|e| ActionError::InvalidDefinition {
+            action: action.name.clone(),
+            message: e.to_string(),
+        })
+    }
+
+    /// ask whether to retry, skip or abort a failed script
+    fn ask_retry(&mut self, action: &Action, code: i32) -> Result<String, ActionError> {
+        let interaction = Interaction {
+            kind: InteractionKind::Select,
+            prompt: format!("'{}' failed with exit code {code}", action.name),
+            out: None,
+            options: Some(vec![
+                "retry".to_string(),
+                "skip".to_string(),
+                "abort".to_string(),
+            ]),
+            default_value: None,
+            ask_if_has_default: None,
+            separator: None,
+        };
+        match interaction.play(None, self.events.as_mut()) {
+            Ok(Response::Text(choice)) => Ok(choice),
+            Ok(_) => Ok("abort".to_string()),
+            Err(e) => Err(prompt_error(action, &e)),
+        }
+    }
+
+    /// where and how the script of an action runs
+    fn script_options(
+        &self,
+        action: &Action,
+        renderer: &Renderer,
+        working_dir: Option<&Path>,
+        varbag: &VarBag,
+    ) -> Result<exec::ScriptOptions, ActionError> {
+        let mut options = exec::ScriptOptions {
+            shell: action.shell.clone(),
+            working_dir: match action.working_dir {
+                Some(ref dir) => {
+                    let dir = renderer.render(dir, varbag).map_err(|e| {
+                        ActionError::InvalidDefinition {
+                            action: action.name.clone(),
+                            message: e.to_string(),
+                        }
+                    })?;
+                    Some(
+                        working_dir
+                            .map_or_else(|| Path::new(&dir).to_path_buf(), |base| base.join(&dir)),
+                    )
+                }
+                None => working_dir.map(std::path::Path::to_path_buf),
+            },
+            capture: match action.capture.unwrap_or_default() {
+                Capture::Off if action.out.is_some() => Capture::On,
+                capture => capture,
+            },
+            prefix: action.output_prefix.clone().unwrap_or_default(),
+            max_capture: action.max_capture.or(self.max_capture),
+            ..exec::ScriptOptions::default()
+        };
+        if let Some(timeout) = action.timeout {
+            options.timeout = Some(Duration::try_from_secs_f64(timeout).map_err(|e| {
+                ActionError::InvalidDefinition {
+                    action: action.name.clone(),
+                    message: format!("invalid 'timeout': {e}"),
+                }
+            })?);
+        }
+
+        // varbag entries as environment variables: city -> $CITY
+        if let Some(export) = action.export_env.as_ref().or(self.export_env.as_ref()) {
+            options.env = export.vars(varbag);
+        }
+        for (name, value) in &action.env {
+            let value =
+                renderer
+                    .render(value, varbag)
+                    .map_err(|e| ActionError::InvalidDefinition {
+                        action: action.name.clone(),
+                        message: format!("invalid 'env.{name}': {e}"),
+                    })?;
+            options.env.insert(name.clone(), value);
+        }
+        Ok(options)
+    }
+
+    fn run_script(
+        &mut self,
+        action: &Action,
+        run: &str,
+        mut options: exec::ScriptOptions,
+        result: &mut ActionResult,
+        renderer: &Renderer,
+        varbag: &mut VarBag,
+    ) -> Result<(), ActionError> {
+        let script = self.render_script(action, run, renderer, varbag)?;
+        let export_env = action.export_env.as_ref().or(self.export_env.as_ref());
+        let exports_secrets =
+            export_env.is_some() && varbag.iter().any(|(k, _)| varbag.is_secret(k));
+
+        // the shell would echo secrets verbatim, and mix its echo into captured stderr,
+        // so in these cases echo a redacted copy instead
+        let redacted = varbag.redact(&script);
+        if let Some(ref observer) = self.output {
+            for line in redacted.trim().lines() {
+                observer.line(&action.name, Stream::Stderr, &format!("+ {line}"));
+            }
+            let observer = observer.clone();
+            let name = action.name.clone();
+            let secrets = varbag.clone();
+            options.on_line = Some(Arc::new(move |stream, line: &str| {
+                observer.line(&name, stream, &secrets.redact(line));
+            }));
+        } else if redacted == script
+            && !exports_secrets
+            && !options.capture.is_captured()
+            && options.shell.is_none()
+        {
+            options.print_commands = true;
+        } else {
+            for line in redacted.trim().lines() {
+                eprintln!("+ {line}");
+            }
+        }
+
+        let working_dir = options
+            .working_dir
+            .as_ref()
+            .map(|dir| dir.display().to_string());
+        let env = redacted_env(&options.env, varbag);
+        let retry = action.retry.clone().unwrap_or_default();
+        let (code, out, err, timing) = loop {
+            let to_error = |e: std::io::Error| ActionError::Script {
+                action: action.name.clone(),
+                message: e.to_string(),
+            };
+            let stopwatch = Stopwatch::start();
+            let running = exec::spawn(&script, &options).map_err(to_error)?;
+            let pid = running.id();
+            self.notify(|o| o.script_spawned(action, pid));
+            let started = Instant::now();
+            let output = running.wait(&self.cancel).map_err(to_error)?;
+            let timing = stopwatch.stop();
+            if let exec::Exit::Code(code) = output.exit {
+                self.notify(|o| o.script_finished(action, code, started.elapsed()));
+            }
+            let retries = u32::try_from(result.attempts.len()).unwrap_or(u32::MAX) + 1;
+            let (code, out, err) = match output.exit {
+                exec::Exit::Code(code) => (code, output.out, output.err),
+                exec::Exit::TimedOut if retry.on_timeout && retries < retry.attempts => {
+                    std::thread::sleep(retry.delay_before(retries));
+                    // a timed out script has no exit code
+                    result.attempts.push(RunResult {
+                        script: redacted.clone(),
+                        code: -1,
+                        out: varbag.redact(&output.out),
+                        err: varbag.redact(&output.err),
+                        timing: Some(timing),
+                        working_dir: working_dir.clone(),
+                        env: env.clone(),
+                    });
+                    continue;
+                }
+                exec::Exit::TimedOut => {
+                    return Err(ActionError::TimedOut {
+                        action: action.name.clone(),
+                        timeout: action.timeout.unwrap_or_default(),
+                    })
+                }
+                exec::Exit::Cancelled => {
+                    return Err(ActionError::Aborted {
+                        action: action.name.clone(),
+                    })
+                }
+                exec::Exit::Interrupted => {
+                    return Err(ActionError::Interrupted {
+                        action: action.name.clone(),
+                    })
+                }
+            };
+            if action.ignore_exit || code == 0 {
+                break (code, out, err, timing);
+            }
+            let failed = RunResult {
+                script: redacted.clone(),
+                code,
+                out: varbag.redact(&out),
+                err: varbag.redact(&err),
+                timing: Some(timing),
+                working_dir: working_dir.clone(),
+                env: env.clone(),
+            };
+
+            if retries < retry.attempts {
+                std::thread::sleep(retry.delay_before(retries));
+                result.attempts.push(failed);
+                continue;
+            }
+            let choice = if retry.ask && !self.non_interactive {
+                self.ask_retry(action, code)?
+            } else {
+                "abort".to_string()
+            };
+            match choice.as_str() {
+                "retry" => result.attempts.push(failed),
+                "skip" => {
+                    result.run = Some(failed);
+                    result.status = ActionStatus::Skipped;
+                    return Ok(());
+                }
+                _ => {
+                    let stderr = failed.err.clone();
+                    result.run = Some(failed);
+                    return Err(ActionError::ScriptFailed {
+                        action: action.name.clone(),
+                        code,
+                        stderr,
+                    });
+                }
+            }
+        };
+
+        // script outcome into varbag: stdout, stderr, exit code and extracted values
+        if let Some(ref script_out) = action.out {
+            script_out
+                .update_varbag(code, &out, &err, varbag)
+                .map_err(|e| ActionError::Output {
+                    action: action.name.clone(),
+                    message: e.to_string(),
+                })?;
+        }
+
+        result.run = Some(RunResult {
+            script: redacted,
+            code,
+            out: varbag.redact(&out),
+            err: varbag.redact(&err),
+            timing: Some(timing),
+            working_dir,
+            env,
+        });
+        if code != 0 {
+            result.status = ActionStatus::IgnoredFailure;
+        }
+        Ok(())
+    }
+}
+
+/// does the `when` expression of an action hold, if it has one
+fn condition_holds(action: &Action, varbag: &VarBag) -> Result<bool, ActionError> {
+    let Some(ref when) = action.when else {
+        return Ok(true);
+    };
+    let condition = Condition::parse(when).map_err(|e| ActionError::InvalidDefinition {
+        action: action.name.clone(),
+        message: format!("invalid 'when': {e}"),
+    })?;
+    Ok(condition.eval(varbag))
+}
+
+/// environment of a script as reported in its result, with secrets redacted
+fn redacted_env(env: &HashMap<String, String>, varbag: &VarBag) -> BTreeMap<String, String> {
+    env.iter()
+        .map(|(k, v)| (k.clone(), varbag.redact(v)))
+        .collect()
+}
+
+/// Ctrl-C in a prompt is a key press, not a signal, so it shows up as a prompt error
+fn prompt_error(action: &Action, e: &anyhow::Error) -> ActionError {
+    match e.downcast_ref::<requestty::ErrorKind>() {
+        Some(requestty::ErrorKind::Interrupted) => ActionError::Interrupted {
+            action: action.name.clone(),
+        },
+        _ => ActionError::Prompt {
+            action: action.name.clone(),
+            message: e.to_string(),
+        },
+    }
+}
+
+/// variables describing a failure to the `on_failure` handlers
+pub(crate) const FAILURE_VARS: [&str; 4] = [
+    "failed_action",
+    "failed_error",
+    "failed_exit_code",
+    "failed_stderr",
+];
+
+/// describe a failure to the `on_failure` handlers, returning the values it shadows,
+/// and whether they were secret
+fn set_failure_vars(error: &ActionError, varbag: &mut VarBag) -> [Option<(String, bool)>; 4] {
+    let (code, stderr) = match error {
+        ActionError::ScriptFailed { code, stderr, .. } => (code.to_string(), stderr.clone()),
+        _ => (String::new(), String::new()),
+    };
+    let values = [error.action().to_string(), error.to_string(), code, stderr];
+    let mut shadowed = [None, None, None, None];
+    for ((var, value), old) in FAILURE_VARS.into_iter().zip(values).zip(&mut shadowed) {
+        let secret = varbag.is_secret(var);
+        *old = varbag
+            .insert(var.to_string(), value)
+            .map(|old| (old, secret));
+    }
+    shadowed
+}
+
+/// remove the failure variables once the `on_failure` handlers ran, restoring what they shadowed
+fn unset_failure_vars(shadowed: [Option<(String, bool)>; 4], varbag: &mut VarBag) {
+    for (var, value) in FAILURE_VARS.into_iter().zip(shadowed) {
+        varbag.remove(var);
+        match value {
+            Some((value, true)) => varbag.insert_secret(var.to_string(), value),
+            Some((value, false)) => varbag.insert(var.to_string(), value),
+            None => None,
+        };
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::REDACTED;
     use insta::assert_debug_snapshot;
-    use requestty_ui::events::KeyCode;
+    use requestty_ui::events::{KeyCode, KeyModifiers};
 
     #[test]
     fn test_interaction() {
@@ -247,7 +1079,7 @@ mod tests {
         ];
         let mut actions = ActionRunner::with_events(events);
         let mut v = VarBag::new();
-        assert_debug_snapshot!(actions
+        insta::assert_yaml_snapshot!(actions
             .run(
                 &actions_defs,
                 Some(Path::new(".")),
@@ -255,7 +1087,10 @@ mod tests {
                 ActionHook::After,
                 None::<&fn(&Action) -> ()>
             )
-            .unwrap());
+            .unwrap(), {
+            "[].timing" => "[timing]",
+            "[].interaction_timing" => "[timing]"
+        });
         assert_debug_snapshot!(v);
     }
 
@@ -292,9 +1127,1433 @@ mod tests {
                 ActionHook::After,
             None::<&fn(&Action) -> ()>)
             .unwrap(),  {
-            "[0].run.err" => ""
+            "[0].run.err" => "",
+            "[].timing" => "[timing]",
+            "[].interaction_timing" => "[timing]",
+            "[].run.timing" => "[timing]"
+        });
+
+        assert_debug_snapshot!(v);
+    }
+
+    #[test]
+    fn test_multiselect() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: multiselect-action
+  interaction:
+    kind: multiselect
+    prompt: select languages
+    options:
+    - rust
+    - go
+    - python
+    separator: " "
+    out: langs
+"#,
+        )
+        .unwrap();
+        let events = vec![
+            KeyCode::Char(' ').into(), // select: rust
+            KeyCode::Down.into(),      //
+            KeyCode::Down.into(),      //
+            KeyCode::Char(' ').into(), // select: python
+            KeyCode::Enter.into(),     //
+        ];
+        let mut actions = ActionRunner::with_events(events);
+        let mut v = VarBag::new();
+        insta::assert_yaml_snapshot!(actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>
+            )
+            .unwrap(), {
+            "[].timing" => "[timing]",
+            "[].interaction_timing" => "[timing]"
+        });
+        assert_debug_snapshot!(v);
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_password() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+    - name: password-action
+      interaction:
+        kind: password
+        prompt: api token?
+        out: token
+      run: echo token={{token}}
+      capture: true
+    "#,
+        )
+        .unwrap();
+        let events = vec![
+            KeyCode::Char('s').into(), // token: 's3cr3t'
+            KeyCode::Char('3').into(), //
+            KeyCode::Char('c').into(), //
+            KeyCode::Char('r').into(), //
+            KeyCode::Char('3').into(), //
+            KeyCode::Char('t').into(), //
+            KeyCode::Enter.into(),     //
+        ];
+        let mut actions = ActionRunner::with_events(events);
+        let mut v = VarBag::new();
+
+        insta::assert_yaml_snapshot!(actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+            None::<&fn(&Action) -> ()>)
+            .unwrap(),  {
+            "[0].run.err" => "",
+            "[].timing" => "[timing]",
+            "[].interaction_timing" => "[timing]",
+            "[].run.timing" => "[timing]"
         });
 
+        assert_eq!(v.get("token").map(String::as_str), Some("s3cr3t"));
         assert_debug_snapshot!(v);
     }
+
+    #[test]
+    fn test_decline() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: skip-action
+  interaction:
+    kind: confirm
+    prompt: run this?
+    out: first
+  run: exit 1
+  on_decline: skip
+  break_if_cancel: true
+- name: break-action
+  interaction:
+    kind: confirm
+    prompt: continue?
+    out: second
+  on_decline: break
+- name: never-action
+  interaction:
+    kind: input
+    prompt: not asked
+    out: never
+"#,
+        )
+        .unwrap();
+        let events = vec![
+            KeyCode::Char('n').into(), // first: n
+            KeyCode::Enter.into(),     //
+            KeyCode::Char('n').into(), // second: n
+            KeyCode::Enter.into(),     //
+        ];
+        let mut actions = ActionRunner::with_events(events);
+        let mut v = VarBag::new();
+        let err = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.error,
+            ActionError::Declined { ref action } if action == "break-action"
+        ));
+        assert_eq!(err.results.len(), 1);
+        assert_eq!(err.results[0].status, ActionStatus::Skipped);
+        assert_debug_snapshot!(v);
+    }
+
+    #[test]
+    fn test_when() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: select-action
+  interaction:
+    kind: select
+    prompt: select transport
+    options:
+    - bus
+    - train
+    out: transport
+- name: bus-action
+  when: transport == "bus"
+  interaction:
+    kind: input
+    prompt: bus line?
+    out: line
+- name: train-action
+  when: transport in ["train", "tram"] and line is empty
+  interaction:
+    kind: input
+    prompt: train station?
+    out: station
+"#,
+        )
+        .unwrap();
+        let events = vec![
+            KeyCode::Down.into(),      // select: train
+            KeyCode::Enter.into(),     //
+            KeyCode::Char('a').into(), // station: 'a'
+            KeyCode::Enter.into(),     //
+        ];
+        let mut actions = ActionRunner::with_events(events);
+        let mut v = VarBag::new();
+        insta::assert_yaml_snapshot!(actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>
+            )
+            .unwrap(), {
+            "[].timing" => "[timing]",
+            "[].interaction_timing" => "[timing]"
+        });
+    }
+
+    #[test]
+    fn test_strict_templates() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: templated-action
+  run: echo {{ city }}
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        let err = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("in action 'templated-action': undefined value"));
+
+        let mut actions = ActionRunner {
+            lenient_templates: true,
+            dry_run: true,
+            ..ActionRunner::default()
+        };
+        let res = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_eq!(res[0].run.as_ref().unwrap().script, "echo ");
+    }
+
+    #[test]
+    fn test_raw_scripts() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: raw-action
+  run: docker ps --format '{{.Id}}'
+  raw: true
+- name: block-action
+  run: echo {% raw %}'{{.Id}}'{% endraw %} {{ city }}
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner {
+            dry_run: true,
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        v.insert("city".to_string(), "tlv".to_string());
+        let scripts = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|r| r.run.unwrap().script)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scripts,
+            vec!["docker ps --format '{{.Id}}'", "echo '{{.Id}}' tlv"]
+        );
+
+        // without `raw`, literal braces are a template syntax error
+        let mut actions = ActionRunner {
+            dry_run: true,
+            ..ActionRunner::default()
+        };
+        assert!(actions
+            .run(
+                &actions_defs[..1]
+                    .iter()
+                    .map(|a| Action {
+                        raw: None,
+                        ..a.clone()
+                    })
+                    .collect::<Vec<_>>(),
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .is_err());
+
+        let mut actions = ActionRunner {
+            dry_run: true,
+            raw_scripts: true,
+            ..ActionRunner::default()
+        };
+        let res = actions
+            .run(
+                &actions_defs[1..],
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_eq!(
+            res[0].run.as_ref().unwrap().script,
+            "echo {% raw %}'{{.Id}}'{% endraw %} {{ city }}"
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_export_env() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: env-action
+  run: echo "$IA_CITY"
+  export_env:
+    prefix: ia_
+  capture: true
+- name: escape-action
+  run: echo {{city}}
+  shell_escape: true
+  capture: true
+- name: default-prefix-action
+  run: echo "$IA_PATH" && command -v sh >/dev/null && echo found
+  export_env: {}
+  capture: true
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        v.insert("city".to_string(), "$(echo injected)".to_string());
+        v.insert("path".to_string(), "/nowhere".to_string());
+        let res = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        let outs = res
+            .iter()
+            .map(|r| r.run.as_ref().unwrap().out.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outs,
+            vec![
+                "$(echo injected)\n",
+                "$(echo injected)\n",
+                "/nowhere\nfound\n"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_script_out() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: compute-action
+  run: |
+    echo '{"name": "interactive-actions", "version": "1.0.1"}'
+    echo branch: main
+    echo oops >&2
+  out:
+    stderr: errors
+    code: code
+    extract:
+    - var: name
+      line: 0
+      json: /name
+    - var: branch
+      regex: 'branch: (\w+)'
+- name: input-action
+  interaction:
+    kind: input
+    prompt: "{{name}} branch?"
+    default_value: "{{branch}}"
+    out: target
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_debug_snapshot!(v);
+    }
+
+    #[test]
+    fn test_answers() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: confirm-action
+  interaction:
+    kind: confirm
+    prompt: are you sure?
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    out: city
+- name: select-action
+  interaction:
+    kind: select
+    prompt: select transport
+    options:
+    - bus
+    - train
+    out: transport
+- name: multiselect-action
+  interaction:
+    kind: multiselect
+    prompt: select languages
+    options:
+    - rust
+    - go
+    - python
+    out: langs
+- name: default-action
+  interaction:
+    kind: input
+    prompt: which country?
+    default_value: israel
+    out: country
+"#,
+        )
+        .unwrap();
+        let answers = answers::Answers::from_yaml(
+            r"
+confirm-action: true
+city: tlv
+transport: train
+langs: [rust, python]
+",
+        )
+        .unwrap();
+        let mut actions = ActionRunner {
+            answers: vec![Box::new(answers)],
+            non_interactive: true,
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_debug_snapshot!(v);
+
+        let mut actions = ActionRunner {
+            answers: vec![Box::new(
+                answers::Answers::from_yaml("transport: plane").unwrap(),
+            )],
+            non_interactive: true,
+            ..ActionRunner::default()
+        };
+        let err = actions
+            .run(
+                &actions_defs[2..],
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"in action 'select-action': invalid answer: 'plane' is not one of the options ["bus", "train"]"#
+        );
+
+        let err = actions
+            .run(
+                &actions_defs[1..],
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "in action 'input-action': no answer was given, and there is no default"
+        );
+    }
+
+    #[test]
+    fn test_answer_sources() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: confirm-action
+  interaction:
+    kind: confirm
+    prompt: are you sure?
+    out: confirm
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    out: city
+- name: multiselect-action
+  interaction:
+    kind: multiselect
+    prompt: select languages
+    options:
+    - rust
+    - go
+    - python
+    out: langs
+"#,
+        )
+        .unwrap();
+        let events = vec![
+            KeyCode::Char(' ').into(), // select: rust
+            KeyCode::Enter.into(),     //
+        ];
+        let mut actions = ActionRunner {
+            answers: vec![
+                Box::new(answers::Answers::from_pairs(["city=tlv"]).unwrap()),
+                Box::new(answers::EnvAnswers::with_vars(
+                    "ia_",
+                    BTreeMap::from([
+                        ("IA_CITY".to_string(), "dallas".to_string()),
+                        ("IA_CONFIRM".to_string(), "yes".to_string()),
+                    ]),
+                )),
+            ],
+            ..ActionRunner::with_events(events)
+        };
+        let mut v = VarBag::new();
+        actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_debug_snapshot!(v);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    default_value: tlv
+    out: city
+- name: run-action
+  run: rm -rf {{city}}
+- name: skipped-action
+  when: city == "dallas"
+  run: echo dallas
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner {
+            dry_run: true,
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        insta::assert_yaml_snapshot!(actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap(), {
+            "[].timing" => "[timing]",
+            "[].interaction_timing" => "[timing]"
+        });
+    }
+
+    #[test]
+    fn test_dry_run_placeholders() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    out: city
+- name: confirm-action
+  interaction:
+    kind: confirm
+    prompt: deploy?
+  run: deploy {{city}}
+"#,
+        )
+        .unwrap();
+        for non_interactive in [false, true] {
+            let mut actions = ActionRunner {
+                dry_run: true,
+                non_interactive,
+                ..ActionRunner::default()
+            };
+            let mut v = VarBag::new();
+            let res = actions
+                .run(
+                    &actions_defs,
+                    Some(Path::new(".")),
+                    &mut v,
+                    ActionHook::After,
+                    None::<&fn(&Action) -> ()>,
+                )
+                .unwrap();
+            assert_eq!(res[1].status, ActionStatus::WouldRun);
+            assert_eq!(res[1].run.as_ref().unwrap().script, "deploy <city>");
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_script_failed() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: ok-action
+  run: echo ok
+  capture: true
+- name: failing-action
+  run: exit 3
+  capture: true
+- name: never-action
+  run: echo never
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        let err = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.error,
+            ActionError::ScriptFailed { ref action, code: 3, .. } if action == "failing-action"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "in action 'failing-action': command returned exit code '3'"
+        );
+        assert_eq!(err.results.len(), 1);
+        assert_eq!(err.results[0].name, "ok-action");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_run_report() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: ok-action
+  run: echo ok
+  capture: true
+- name: failing-action
+  run: exit 3
+  capture: true
+- name: never-action
+  run: echo never
+- name: before-action
+  hook: before
+  run: echo before
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        let report = actions.run_report(
+            &actions_defs,
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert!(!report.is_ok());
+        assert_eq!(report.failed_action(), Some("failing-action"));
+        assert_eq!(report.results.len(), 1);
+        assert_eq!(report.pending, vec!["never-action"]);
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_resume() {
+        let dir = std::env::temp_dir().join(format!(
+            "interactive-actions-test-resume-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    out: city
+- name: token-action
+  interaction:
+    kind: password
+    prompt: token?
+    out: token
+  run: echo ran >> runs
+- name: flaky-action
+  run: test -e flag && test "$IA_TOKEN" = s3cr3t
+  export_env: {}
+"#,
+        )
+        .unwrap();
+        let store = checkpoint::CheckpointStore::new(&dir.join("checkpoint.yaml"));
+        let mut actions = ActionRunner {
+            answers: vec![Box::new(
+                answers::Answers::from_pairs(["city=tlv", "token=s3cr3t"]).unwrap(),
+            )],
+            non_interactive: true,
+            checkpoint: Some(store.clone()),
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        let report = actions.run_report(
+            &actions_defs,
+            Some(&dir),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert_eq!(report.failed_action(), Some("flaky-action"));
+        let saved = store.load().unwrap().unwrap();
+        assert_eq!(saved.completed, vec!["input-action", "token-action"]);
+        assert!(!saved.varbag.contains_key("token"));
+
+        // a checkpoint of other actions is not resumed, however it was loaded
+        let mut actions = ActionRunner {
+            resume: store.load().unwrap(),
+            ..ActionRunner::default()
+        };
+        let report = actions.run_report(
+            &actions_defs[..2],
+            Some(&dir),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert!(matches!(
+            report.error,
+            Some(ActionError::StaleCheckpoint { ref action }) if action == "input-action"
+        ));
+        assert_eq!(report.pending.len(), 2);
+
+        // without answers, input-action would fail if it ran again. the token is asked
+        // for again, but the script of token-action does not run again
+        std::fs::write(dir.join("flag"), "").unwrap();
+        let mut actions = ActionRunner {
+            answers: vec![Box::new(
+                answers::Answers::from_pairs(["token=s3cr3t"]).unwrap(),
+            )],
+            non_interactive: true,
+            resume: store.load_for(&actions_defs, &ActionHook::After).unwrap(),
+            checkpoint: Some(store.clone()),
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        let results = actions
+            .run(
+                &actions_defs,
+                Some(&dir),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(v.get("city").map(String::as_str), Some("tlv"));
+        assert!(v.is_secret("token"));
+        assert_eq!(std::fs::read_to_string(dir.join("runs")).unwrap(), "ran\n");
+        assert!(store.load().unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_failure_handlers() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: scaffold-action
+  run: echo oops >&2; exit 2
+  capture: true
+  on_failure:
+  - name: cleanup-action
+    run: echo "{{failed_action}} {{failed_exit_code}} {{failed_stderr}}"
+    capture: true
+  finally:
+  - name: action-finally
+    run: echo action done
+    capture: true
+- name: never-action
+  run: echo never
+  finally:
+  - name: never-finally
+    run: echo never
+"#,
+        )
+        .unwrap();
+        let handlers: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: run-cleanup
+  run: echo {{failed_action}}; exit 1
+  capture: true
+- name: run-finally
+  run: echo run done
+  capture: true
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner {
+            on_failure: handlers[..1].to_vec(),
+            finally: handlers[1..].to_vec(),
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        v.insert("failed_action".to_string(), "earlier".to_string());
+        v.insert_secret("failed_stderr".to_string(), "s3cr3t".to_string());
+        let report = actions.run_report(
+            &actions_defs,
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert_eq!(report.failed_action(), Some("scaffold-action"));
+        assert_eq!(report.pending, vec!["never-action"]);
+        let outs = report
+            .handlers
+            .iter()
+            .map(|r| (r.name.as_str(), r.run.as_ref().unwrap().out.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outs,
+            vec![
+                ("cleanup-action", "scaffold-action 2 oops\n\n"),
+                ("action-finally", "action done\n"),
+                ("run-cleanup", "scaffold-action\n"),
+                ("run-finally", "run done\n"),
+            ]
+        );
+        assert_eq!(report.handlers[2].status, ActionStatus::Failed);
+        assert_eq!(report.handler_errors.len(), 1);
+        // the failure variables are gone once the handlers ran, or back to what they were
+        assert_eq!(v.get("failed_action").map(String::as_str), Some("earlier"));
+        assert!(!v.is_secret("failed_action"));
+        assert_eq!(v.get("failed_stderr").map(String::as_str), Some("s3cr3t"));
+        assert!(v.is_secret("failed_stderr"));
+        assert!(!v.contains_key("failed_error"));
+        assert_eq!(report.handler_errors[0].action(), "run-cleanup");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_retry() {
+        let dir = std::env::temp_dir().join(format!(
+            "interactive-actions-test-retry-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: flaky-action
+  run: |
+    n=$(($(cat count 2>/dev/null || echo 0) + 1))
+    echo $n > count
+    echo attempt $n
+    test $n -ge 3
+  capture: true
+  retry:
+    attempts: 3
+    delay: 0.01
+    backoff: 2
+- name: ask-action
+  run: exit 4
+  capture: true
+  retry:
+    ask: true
+"#,
+        )
+        .unwrap();
+        let events = vec![
+            KeyCode::Down.into(),  // skip
+            KeyCode::Enter.into(), //
+        ];
+        let mut actions = ActionRunner::with_events(events);
+        let mut v = VarBag::new();
+        let results = actions
+            .run(
+                &actions_defs,
+                Some(&dir),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        let attempts = results[0]
+            .attempts
+            .iter()
+            .chain(results[0].run.as_ref())
+            .map(|run| (run.code, run.out.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attempts,
+            vec![(1, "attempt 1\n"), (1, "attempt 2\n"), (0, "attempt 3\n")]
+        );
+        assert_eq!(results[0].status, ActionStatus::Ok);
+        assert_eq!(results[1].status, ActionStatus::Skipped);
+        assert_eq!(results[1].run.as_ref().unwrap().code, 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let retry = data::Retry {
+            delay: 1.0,
+            backoff: 2.0,
+            ..data::Retry::default()
+        };
+        assert_eq!(retry.delay_before(3), std::time::Duration::from_secs(4));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_timeout() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: hung-action
+  run: sleep 10
+  timeout: 0.1
+  retry:
+    attempts: 3
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        let err = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "in action 'hung-action': timed out after 0.1s"
+        );
+        // timeouts fail at once, unless the retry says to retry them too
+        let report = actions.run_report(
+            &actions_defs,
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert!(report.failed.unwrap().attempts.is_empty());
+        let dir = std::env::temp_dir().join(format!(
+            "interactive-actions-test-timeout-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let retried: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: slow-start-action
+  run: |
+    n=$(($(cat count 2>/dev/null || echo 0) + 1))
+    echo $n > count
+    test $n -ge 2 || sleep 10
+    echo attempt $n
+  capture: true
+  timeout: 0.5
+  retry:
+    attempts: 2
+    on_timeout: true
+"#,
+        )
+        .unwrap();
+        let res = actions
+            .run(
+                &retried,
+                Some(&dir),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_eq!(res[0].attempts.len(), 1);
+        assert_eq!(res[0].attempts[0].code, -1);
+        assert_eq!(res[0].run.as_ref().unwrap().out, "attempt 2\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // a cancel requested before the run stops it at the first action
+        actions.cancel.cancel();
+        let err = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert!(matches!(err.error, ActionError::Aborted { .. }));
+        assert!(!actions.cancel.is_cancelled());
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_interrupt() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: slow-action
+  run: exec sleep 10
+- name: never-action
+  run: echo never
+"#,
+        )
+        .unwrap();
+        let finally: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: cleanup-action
+  run: echo cleanup
+  capture: true
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner {
+            handle_interrupt: true,
+            finally,
+            ..ActionRunner::default()
+        };
+        std::thread::spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            // SAFETY: SIGINT to this process, which traps it while the runner runs
+            unsafe {
+                libc::kill(libc::getpid(), libc::SIGINT);
+            }
+        });
+        let mut v = VarBag::new();
+        let report = actions.run_report(
+            &actions_defs,
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert!(matches!(
+            report.error,
+            Some(ActionError::Interrupted { ref action }) if action == "slow-action"
+        ));
+        assert_eq!(report.pending, vec!["never-action"]);
+        assert_eq!(report.handlers[0].run.as_ref().unwrap().out, "cleanup\n");
+
+        // Ctrl-C in a prompt
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    out: city
+"#,
+        )
+        .unwrap();
+        let events = vec![KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)];
+        let mut actions = ActionRunner::with_events(events);
+        let err = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap_err();
+        assert!(matches!(err.error, ActionError::Interrupted { .. }));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_tee() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: tee-action
+  run: echo streamed
+  capture: tee
+  output_prefix: "[tee] "
+"#,
+        )
+        .unwrap();
+        assert_eq!(actions_defs[0].capture, Some(Capture::Tee));
+        assert!(serde_yaml::from_str::<Vec<Action>>("- {name: a, capture: maybe}").is_err());
+
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        let results = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_eq!(results[0].run.as_ref().unwrap().out, "streamed\n");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_output_observer() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: build-action
+  run: |
+    echo compiling
+    echo "token $IA_TOKEN" >&2
+  export_env:
+    prefix: ia_
+"#,
+        )
+        .unwrap();
+        let lines = Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = lines.clone();
+        let mut actions = ActionRunner {
+            output: Some(Arc::new(move |action: &str, stream, line: &str| {
+                sink.lock()
+                    .unwrap()
+                    .push(format!("{action} {stream:?}: {line}"));
+            })),
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        v.insert_secret("token".to_string(), "s3cr3t".to_string());
+        let results = actions
+            .run(
+                &actions_defs,
+                Some(Path::new(".")),
+                &mut v,
+                ActionHook::After,
+                None::<&fn(&Action) -> ()>,
+            )
+            .unwrap();
+        assert_eq!(results[0].run.as_ref().unwrap().out, "");
+        let mut lines = lines.lock().unwrap().clone();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "build-action Stderr: + echo \"token $IA_TOKEN\" >&2",
+                "build-action Stderr: + echo compiling",
+                "build-action Stderr: token ********",
+                "build-action Stdout: compiling",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_run_observer() {
+        struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+        impl RunObserver for Recorder {
+            fn workflow_start(&mut self, actions: &[&Action]) {
+                self.0.borrow_mut().push(format!("start {}", actions.len()));
+            }
+            fn workflow_end(&mut self, report: &RunReport) {
+                self.0.borrow_mut().push(format!("end {}", report.is_ok()));
+            }
+            fn action_start(&mut self, action: &Action) {
+                self.0.borrow_mut().push(format!("action {}", action.name));
+            }
+            fn interaction_answered(&mut self, _action: &Action, response: &Response) {
+                self.0.borrow_mut().push(format!("answered {response:?}"));
+            }
+            fn script_spawned(&mut self, action: &Action, _pid: u32) {
+                self.0.borrow_mut().push(format!("spawned {}", action.name));
+            }
+            fn script_finished(&mut self, action: &Action, code: i32, _duration: Duration) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("finished {} {code}", action.name));
+            }
+            fn action_skipped(&mut self, action: &Action) {
+                self.0.borrow_mut().push(format!("skipped {}", action.name));
+            }
+            fn action_failed(&mut self, action: &Action, _error: &ActionError) {
+                self.0.borrow_mut().push(format!("failed {}", action.name));
+            }
+            fn warning(&mut self, message: &str) {
+                self.0.borrow_mut().push(format!("warning {message}"));
+            }
+        }
+
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: input-action
+  interaction:
+    kind: input
+    prompt: which city?
+    default_value: tlv
+    out: city
+- name: skipped-action
+  when: city == "dallas"
+  run: echo dallas
+- name: failing-action
+  run: exit 2
+- name: never-action
+  run: echo never
+"#,
+        )
+        .unwrap();
+        let events = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let mut actions = ActionRunner {
+            non_interactive: true,
+            observer: Some(Box::new(Recorder(events.clone()))),
+            ..ActionRunner::default()
+        };
+        let mut v = VarBag::new();
+        actions.run_report(
+            &actions_defs,
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert_debug_snapshot!(events.borrow());
+
+        // problems which do not stop the run are reported, rather than printed
+        let dir = std::env::temp_dir().join(format!(
+            "interactive-actions-test-observer-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let events = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let mut actions = ActionRunner {
+            checkpoint: Some(CheckpointStore::new(&dir)),
+            observer: Some(Box::new(Recorder(events.clone()))),
+            ..ActionRunner::default()
+        };
+        let report = actions.run_report(
+            &[],
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+        assert!(report.is_ok());
+        assert!(events.borrow()[1].starts_with("warning cannot remove checkpoint"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_result_metadata() {
+        let actions_defs: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: ignored-action
+  run: exit 4
+  ignore_exit: true
+  working_dir: src
+  export_env: {}
+- name: failing-action
+  run: exit 2
+  capture: true
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        v.insert("city".to_string(), "tlv".to_string());
+        v.insert_secret("token".to_string(), "s3cr3t".to_string());
+        let report = actions.run_report(
+            &actions_defs,
+            Some(Path::new(".")),
+            &mut v,
+            ActionHook::After,
+            None::<&fn(&Action) -> ()>,
+        );
+
+        let ignored = &report.results[0];
+        assert_eq!(ignored.status, ActionStatus::IgnoredFailure);
+        assert!(ignored.timing.is_some());
+        assert!(ignored.interaction_timing.is_none());
+        let run = ignored.run.as_ref().unwrap();
+        assert_eq!(run.code, 4);
+        assert!(run.timing.is_some());
+        assert_eq!(run.working_dir.as_deref(), Some("./src"));
+        assert_eq!(
+            run.env.iter().collect::<Vec<_>>(),
+            vec![
+                (&"IA_CITY".to_string(), &"tlv".to_string()),
+                (&"IA_TOKEN".to_string(), &REDACTED.to_string())
+            ]
+        );
+
+        let failed = report.failed.as_ref().unwrap();
+        assert_eq!(failed.name, "failing-action");
+        assert_eq!(failed.status, ActionStatus::Failed);
+        assert_eq!(failed.run.as_ref().unwrap().code, 2);
+        assert!(failed.timing.is_some());
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_run_workflow() {
+        let workflow: Workflow = serde_yaml::from_str(
+            r#"
+name: greet
+vars:
+  city: tlv
+  greeting: hello
+defaults:
+  env:
+    GREETING: "{{greeting}}"
+  capture: true
+before:
+- name: setup-action
+  run: echo setup
+after:
+- name: greet-action
+  run: echo "$GREETING {{city}}"
+finally:
+- name: finally-action
+  run: echo done
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        v.insert("city".to_string(), "dallas".to_string());
+        let report =
+            actions.run_workflow_report(&workflow, Some(Path::new(".")), &mut v, ActionHook::After);
+        assert!(report.is_ok());
+        let outs = report
+            .results
+            .iter()
+            .chain(report.handlers.iter())
+            .map(|r| (r.name.as_str(), r.run.as_ref().unwrap().out.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outs,
+            vec![
+                ("greet-action", "hello dallas\n"),
+                ("finally-action", "done\n")
+            ]
+        );
+        assert!(actions.finally.is_empty());
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_run_workflow_hooks() {
+        let workflow: Workflow = serde_yaml::from_str(
+            r#"
+before:
+- name: setup-action
+  run: echo setup
+after:
+- name: build-action
+  run: echo build
+finally:
+- name: cleanup-action
+  run: echo cleanup
+- name: unlock-action
+  hook: before
+  run: echo unlock
+"#,
+        )
+        .unwrap();
+        let mut actions = ActionRunner::default();
+        let mut v = VarBag::new();
+        let runs = [ActionHook::Before, ActionHook::After].map(|hook| {
+            let report = actions.run_workflow_report(&workflow, Some(Path::new(".")), &mut v, hook);
+            assert!(report.is_ok());
+            report
+                .results
+                .iter()
+                .chain(report.handlers.iter())
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(
+            runs,
+            [
+                vec!["setup-action", "unlock-action"],
+                vec!["build-action", "cleanup-action"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_varbag_map() {
+        let map = BTreeMap::from([("city".to_string(), "tlv".to_string())]);
+        let mut v = VarBag::from(map.clone());
+        v.insert_secret("token".to_string(), "s3cr3t".to_string());
+        assert_eq!(v.keys().collect::<Vec<_>>(), vec!["city", "token"]);
+        assert_eq!(v["city"], "tlv");
+        let back: BTreeMap<String, String> = v.into();
+        assert_eq!(back.len(), 2);
+    }
 }
@@ -0,0 +1,341 @@
+//!
+//! `interactive-actions`: run action files from the command line
+//!
+//! ```text
+//! interactive-actions run setup.yaml --answers answers.yaml --set city=tlv
+//! interactive-actions dry-run setup.yaml --hook before
+//! interactive-actions validate setup.toml
+//! ```
+//!
+//! Exit status: 0 when all actions completed, 1 when an action failed, 2 for
+//! invalid files and definitions, 3 when the user stopped the run, and 130 on Ctrl-C.
+//!
+use anyhow::{Context, Result};
+use clap::{AppSettings, Arg, ArgMatches, Command};
+use interactive_actions::answers::Answers;
+use interactive_actions::data::{Action, ActionHook, ActionStatus, VarBag};
+use interactive_actions::error::ActionError;
+use interactive_actions::observer::ConsoleObserver;
+use interactive_actions::report::JsonReport;
+use interactive_actions::validate::{validate_workflow, Severity};
+use interactive_actions::workflow::Workflow;
+use interactive_actions::ActionRunner;
+use std::path::Path;
+use std::process::exit;
+
+const EXIT_FAILED: i32 = 1;
+const EXIT_INVALID: i32 = 2;
+const EXIT_STOPPED: i32 = 3;
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Parse an action file: a list of actions, or a [`Workflow`] document, which is the
+/// only form TOML allows. The form is picked up front, so errors point into it
+fn parse(text: &str, extension: Option<&str>) -> Result<Workflow> {
+    Ok(match extension {
+        Some("toml") => toml::from_str(text)?,
+        Some("json") if serde_json::from_str::<serde_json::Value>(text)?.is_array() => {
+            Workflow::from(serde_json::from_str::<Vec<Action>>(text)?)
+        }
+        Some("json") => serde_json::from_str(text)?,
+        _ if serde_yaml::from_str::<serde_yaml::Value>(text)?.is_sequence() => {
+            Workflow::from(serde_yaml::from_str::<Vec<Action>>(text)?)
+        }
+        _ => serde_yaml::from_str(text)?,
+    })
+}
+
+/// Load a workflow from a YAML, JSON or TOML file, by its extension
+fn load(path: &Path) -> Result<Workflow> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read action file '{}'", path.display()))?;
+    let workflow = parse(&text, path.extension().and_then(|ext| ext.to_str()))
+        .with_context(|| format!("cannot parse action file '{}'", path.display()))?;
+    if workflow.all_actions().is_empty() {
+        anyhow::bail!("action file '{}' has no actions", path.display());
+    }
+    Ok(workflow)
+}
+
+/// exit status for the error which stopped a run
+fn exit_code(error: &ActionError) -> i32 {
+    match error {
+        ActionError::Interrupted { .. } => EXIT_INTERRUPTED,
+        ActionError::Cancelled { .. }
+        | ActionError::Declined { .. }
+        | ActionError::Aborted { .. } => EXIT_STOPPED,
+        ActionError::InvalidDefinition { .. } | ActionError::StaleCheckpoint { .. } => EXIT_INVALID,
+        ActionError::ScriptFailed { .. }
+        | ActionError::TimedOut { .. }
+        | ActionError::Script { .. }
+        | ActionError::Prompt { .. }
+        | ActionError::Answer { .. }
+        | ActionError::Output { .. }
+        | ActionError::Checkpoint { .. } => EXIT_FAILED,
+    }
+}
+
+fn cli() -> Command<'static> {
+    let file = Arg::new("file")
+        .help("action file: YAML, JSON, or TOML by its extension")
+        .required(true);
+    let run_args = [
+        Arg::new("hook")
+            .long("hook")
+            .help("run the actions of this hook")
+            .possible_values(["before", "after"])
+            .default_value("after"),
+        Arg::new("cwd")
+            .short('C')
+            .long("cwd")
+            .help("working directory of run scripts")
+            .takes_value(true),
+        Arg::new("answers")
+            .short('a')
+            .long("answers")
+            .help("YAML or JSON file answering interactions ahead of time")
+            .takes_value(true)
+            .multiple_occurrences(true),
+        Arg::new("set")
+            .short('s')
+            .long("set")
+            .help("set a variable, answering the interaction which outputs it")
+            .value_name("KEY=VALUE")
+            .takes_value(true)
+            .multiple_occurrences(true),
+        Arg::new("non-interactive")
+            .long("non-interactive")
+            .help("never prompt: use answers and defaults, or fail"),
+        Arg::new("json")
+            .long("json")
+            .help("write a JSON report to this file")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::new("junit")
+            .long("junit")
+            .help("write a JUnit XML report to this file")
+            .value_name("FILE")
+            .takes_value(true),
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .help("do not log actions as they run"),
+    ];
+    Command::new("interactive-actions")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Run actions and interactions defined declaratively")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            Command::new("run")
+                .about("run the actions of a file")
+                .arg(file.clone())
+                .args(run_args.clone()),
+        )
+        .subcommand(
+            Command::new("dry-run")
+                .about(
+                    "print the scripts which would run, answering interactions without prompting",
+                )
+                .arg(file.clone())
+                .args(run_args),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("check the actions of a file without running them")
+                .arg(file),
+        )
+}
+
+fn main() {
+    let matches = cli().get_matches();
+    let code = match matches.subcommand() {
+        Some(("run", sm)) => run(sm, false),
+        Some(("dry-run", sm)) => run(sm, true),
+        Some(("validate", sm)) => check(sm),
+        _ => unreachable!("subcommand is required"),
+    };
+    exit(code.unwrap_or_else(|e| {
+        eprintln!("error: {e:?}");
+        EXIT_INVALID
+    }));
+}
+
+fn check(matches: &ArgMatches) -> Result<i32> {
+    let workflow = load(Path::new(matches.value_of("file").unwrap_or_default()))?;
+    let diagnostics = validate_workflow(&workflow);
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    eprintln!(
+        "{} action(s), {errors} error(s), {} warning(s)",
+        workflow.all_actions().len(),
+        diagnostics.len() - errors
+    );
+    Ok(if errors == 0 { 0 } else { EXIT_INVALID })
+}
+
+fn run(matches: &ArgMatches, dry_run: bool) -> Result<i32> {
+    let workflow = load(Path::new(matches.value_of("file").unwrap_or_default()))?;
+    let hook = match matches.value_of("hook") {
+        Some("before") => ActionHook::Before,
+        _ => ActionHook::After,
+    };
+
+    // `--set` values are variables, and answers which take precedence over answers files
+    let set = Answers::from_pairs(matches.values_of("set").into_iter().flatten())?;
+    let mut varbag = VarBag::new();
+    for pair in matches.values_of("set").into_iter().flatten() {
+        if let Some((key, value)) = pair.split_once('=') {
+            varbag.insert(key.trim().to_string(), value.to_string());
+        }
+    }
+    let mut runner = ActionRunner {
+        answers: vec![Box::new(set)],
+        non_interactive: matches.is_present("non-interactive"),
+        dry_run,
+        handle_interrupt: true,
+        ..ActionRunner::default()
+    };
+    for path in matches.values_of("answers").into_iter().flatten() {
+        runner
+            .answers
+            .push(Box::new(Answers::from_file(Path::new(path))?));
+    }
+    if !matches.is_present("quiet") {
+        runner.observer = Some(Box::new(ConsoleObserver::default()));
+    }
+
+    let report = runner.run_workflow_report(
+        &workflow,
+        matches.value_of("cwd").map(Path::new),
+        &mut varbag,
+        hook,
+    );
+
+    if let Some(path) = matches.value_of("json") {
+        std::fs::write(path, JsonReport::from(&report).to_json()?)
+            .with_context(|| format!("cannot write report '{path}'"))?;
+    }
+    if let Some(path) = matches.value_of("junit") {
+        let suite = if workflow.name.is_empty() {
+            Path::new(matches.value_of("file").unwrap_or_default())
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        } else {
+            workflow.name.clone()
+        };
+        std::fs::write(path, report.to_junit(&suite))
+            .with_context(|| format!("cannot write report '{path}'"))?;
+    }
+    if dry_run {
+        for result in &report.results {
+            match (&result.status, &result.run) {
+                (ActionStatus::WouldRun, Some(run)) => {
+                    println!("# {}\n{}", result.name, run.script.trim_end());
+                }
+                (ActionStatus::Skipped, _) => println!("# {}: skipped", result.name),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(match report.error {
+        None => 0,
+        Some(ref error) => {
+            eprintln!("error: {error}");
+            exit_code(error)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_load() {
+        let dir =
+            std::env::temp_dir().join(format!("interactive-actions-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("actions.yaml", "- name: build\n  run: make\n"),
+            (
+                "actions.json",
+                r#"{"actions": [{"name": "build", "run": "make"}]}"#,
+            ),
+            (
+                "actions.toml",
+                "name = \"ci\"\n[[after]]\nname = \"build\"\nrun = \"make\"\n",
+            ),
+        ];
+        for (name, text) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            let actions = load(&path).unwrap().all_actions();
+            assert_eq!(actions.len(), 1, "{name}");
+            assert_eq!(actions[0].run.as_deref(), Some("make"), "{name}");
+        }
+        assert!(load(&dir.join("missing.yaml")).is_err());
+
+        // documents which are not action files, or have no actions, are invalid
+        let files = [
+            ("unknown.yaml", "steps:\n- name: build\n  run: make\n"),
+            ("empty.yaml", "[]\n"),
+            ("empty.json", "{}"),
+            (
+                "hooks.toml",
+                "[[finally]]\nname = \"clean\"\nrun = \"make clean\"\n",
+            ),
+            ("defaults.yaml", "defaults:\n  shel: bash -c\nactions: []\n"),
+        ];
+        for (name, text) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            assert!(load(&path).is_err(), "{name}");
+        }
+        let err = load(&dir.join("unknown.yaml")).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("unknown field `steps`"),
+            "{err:#}"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cli() {
+        cli().debug_assert();
+        let matches = cli()
+            .try_get_matches_from([
+                "interactive-actions",
+                "run",
+                "setup.yaml",
+                "--set",
+                "city=tlv",
+                "-s",
+                "transport=train",
+                "--hook",
+                "before",
+            ])
+            .unwrap();
+        let (_, sm) = matches.subcommand().unwrap();
+        assert_eq!(
+            sm.values_of("set").unwrap().collect::<Vec<_>>(),
+            vec!["city=tlv", "transport=train"]
+        );
+        assert_eq!(sm.value_of("hook"), Some("before"));
+        assert!(cli()
+            .try_get_matches_from([
+                "interactive-actions",
+                "run",
+                "setup.yaml",
+                "--hook",
+                "during"
+            ])
+            .is_err());
+    }
+}
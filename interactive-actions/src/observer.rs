@@ -0,0 +1,219 @@
+//!
+//! Observing a run as it happens, e.g. for spinners, timing reports, telemetry, or
+//! rendering script output in a TUI
+//!
+//! ```no_run
+//! use interactive_actions::observer::ConsoleObserver;
+//! use interactive_actions::ActionRunner;
+//!
+//! let runner = ActionRunner {
+//!     observer: Some(Box::new(ConsoleObserver::default())),
+//!     ..ActionRunner::default()
+//! };
+//! ```
+//!
+//! Script output, line by line:
+//!
+//! ```no_run
+//! use interactive_actions::observer::Stream;
+//! use interactive_actions::ActionRunner;
+//! use std::sync::Arc;
+//!
+//! let runner = ActionRunner {
+//!     output: Some(Arc::new(|action: &str, stream: Stream, line: &str| {
+//!         println!("{action} {stream:?}: {line}");
+//!     })),
+//!     ..ActionRunner::default()
+//! };
+//! ```
+//!
+use crate::data::{Action, Response};
+use crate::error::ActionError;
+use crate::report::RunReport;
+use serde_derive::{Deserialize, Serialize};
+use std::io::Write;
+use std::time::Duration;
+
+///
+/// Output stream of a script
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stream {
+    /// standard output
+    #[serde(rename = "stdout")]
+    Stdout,
+
+    /// standard error
+    #[serde(rename = "stderr")]
+    Stderr,
+}
+
+///
+/// Receives every line of script output as it arrives. Called from the threads
+/// reading the script output, so lines of the two streams may interleave.
+///
+pub trait OutputObserver: Send + Sync {
+    /// a line of output of `action`'s script, without its line ending, with secrets redacted
+    fn line(&self, action: &str, stream: Stream, line: &str);
+}
+
+impl<F> OutputObserver for F
+where
+    F: Fn(&str, Stream, &str) + Send + Sync,
+{
+    fn line(&self, action: &str, stream: Stream, line: &str) {
+        self(action, stream, line);
+    }
+}
+
+///
+/// Receives the events of a run, in order. Every event does nothing by default,
+/// so implement only the ones of interest.
+///
+#[allow(unused_variables)]
+pub trait RunObserver {
+    /// the run starts, with the actions of the hook being run
+    fn workflow_start(&mut self, actions: &[&Action]) {}
+
+    /// the run ended, after the `on_failure` and `finally` handlers
+    fn workflow_end(&mut self, report: &RunReport) {}
+
+    /// an action starts, after its `when` expression held
+    fn action_start(&mut self, action: &Action) {}
+
+    /// the interaction of an action was answered, by the user or ahead of time
+    fn interaction_answered(&mut self, action: &Action, response: &Response) {}
+
+    /// the script of an action was started, as process `pid`. called for every attempt
+    fn script_spawned(&mut self, action: &Action, pid: u32) {}
+
+    /// the script of an action ended with an exit code. called for every attempt
+    fn script_finished(&mut self, action: &Action, code: i32, duration: Duration) {}
+
+    /// an action was skipped: its `when` expression did not hold, it completed in
+    /// the checkpoint being resumed, its confirm was declined with `on_decline: skip`,
+    /// or the user chose to skip it after it failed
+    fn action_skipped(&mut self, action: &Action) {}
+
+    /// an action failed, or a cancel broke out of it
+    fn action_failed(&mut self, action: &Action, error: &ActionError) {}
+
+    /// a problem which does not stop the run, e.g. interrupts cannot be trapped,
+    /// or the checkpoint of a completed run cannot be removed
+    fn warning(&mut self, message: &str) {}
+}
+
+///
+/// Ignores every event
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopObserver;
+
+impl RunObserver for NoopObserver {}
+
+///
+/// Logs actions, their outcome and timing, one line per event, to stderr by default
+///
+#[derive(Debug)]
+pub struct ConsoleObserver<W: Write = std::io::Stderr> {
+    out: W,
+}
+
+impl Default for ConsoleObserver {
+    fn default() -> Self {
+        Self::new(std::io::stderr())
+    }
+}
+
+impl<W: Write> ConsoleObserver<W> {
+    /// log to `out`
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// the writer logged to
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn log(&mut self, line: &str) {
+        let _ = writeln!(self.out, "{line}");
+    }
+}
+
+impl<W: Write> RunObserver for ConsoleObserver<W> {
+    fn workflow_start(&mut self, actions: &[&Action]) {
+        self.log(&format!("running {} action(s)", actions.len()));
+    }
+
+    fn workflow_end(&mut self, report: &RunReport) {
+        match report.error {
+            None => self.log(&format!(
+                "done, {} action(s) completed",
+                report.results.len()
+            )),
+            Some(ref error) => self.log(&format!(
+                "stopped, {} action(s) completed, {} not run: {error}",
+                report.results.len(),
+                report.pending.len()
+            )),
+        }
+    }
+
+    fn action_start(&mut self, action: &Action) {
+        self.log(&format!("==> {}", action.name));
+    }
+
+    fn script_finished(&mut self, action: &Action, code: i32, duration: Duration) {
+        self.log(&format!(
+            "<== {}: exit code {code} in {:.2}s",
+            action.name,
+            duration.as_secs_f64()
+        ));
+    }
+
+    fn action_skipped(&mut self, action: &Action) {
+        self.log(&format!("--- {}: skipped", action.name));
+    }
+
+    fn action_failed(&mut self, _action: &Action, error: &ActionError) {
+        self.log(&format!("!!! {error}"));
+    }
+
+    fn warning(&mut self, message: &str) {
+        self.log(&format!("warning: {message}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_console_observer() {
+        let actions: Vec<Action> = serde_yaml::from_str("[{name: build}, {name: deploy}]").unwrap();
+        let mut console = ConsoleObserver::new(vec![]);
+        console.workflow_start(&actions.iter().collect::<Vec<_>>());
+        console.action_start(&actions[0]);
+        console.script_finished(&actions[0], 0, Duration::from_millis(1500));
+        console.action_skipped(&actions[1]);
+        console.action_failed(
+            &actions[1],
+            &ActionError::Aborted {
+                action: "deploy".to_string(),
+            },
+        );
+        console.warning("cannot handle interrupts");
+        assert_eq!(
+            String::from_utf8(console.into_inner()).unwrap(),
+            "running 2 action(s)
+==> build
+<== build: exit code 0 in 1.50s
+--- deploy: skipped
+!!! in action 'deploy': aborted
+warning: cannot handle interrupts
+"
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//!
+//! Reports of running a list of actions, and writers turning them into JUnit XML
+//! or JSON for CI dashboards
+//!
+//! ```no_run
+//! use interactive_actions::data::{Action, ActionHook, VarBag};
+//! use interactive_actions::report::JsonReport;
+//! use interactive_actions::ActionRunner;
+//!
+//! # let actions: Vec<Action> = vec![];
+//! let mut runner = ActionRunner {
+//!     non_interactive: true,
+//!     ..ActionRunner::default()
+//! };
+//! let mut v = VarBag::new();
+//! let report = runner.run_report(&actions, None, &mut v, ActionHook::After, None::<fn(&Action) -> ()>);
+//! std::fs::write("report.xml", report.to_junit("provisioning")).unwrap();
+//! std::fs::write("report.json", JsonReport::from(&report).to_json().unwrap()).unwrap();
+//! ```
+//!
+use crate::data::{ActionResult, ActionStatus};
+use crate::error::{ActionError, RunError};
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+///
+/// Outcome of running actions: what completed, what failed, and what never ran.
+/// Unlike a `Result`, it keeps the results of completed actions whatever happens.
+///
+#[derive(Debug)]
+pub struct RunReport {
+    /// results of the actions which completed, in order
+    pub results: Vec<ActionResult>,
+    /// the error which stopped the run, if any. see [`ActionError::action`] for the failing action
+    pub error: Option<ActionError>,
+    /// result of the action which failed, with status [`ActionStatus::Failed`](crate::data::ActionStatus::Failed)
+    pub failed: Option<ActionResult>,
+    /// names of the actions which never ran because of the error
+    pub pending: Vec<String>,
+    /// results of the `on_failure` and `finally` handlers which ran, in order, including failed ones
+    pub handlers: Vec<ActionResult>,
+    /// errors of handlers. a failing handler does not stop the other handlers
+    pub handler_errors: Vec<ActionError>,
+}
+
+impl RunReport {
+    /// did all actions complete
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// name of the action which failed, if any
+    pub fn failed_action(&self) -> Option<&str> {
+        self.error.as_ref().map(ActionError::action)
+    }
+
+    /// Convert into the results, or the error along with the completed results
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an action failed
+    pub fn into_result(self) -> Result<Vec<ActionResult>, RunError> {
+        match self.error {
+            None => Ok(self.results),
+            Some(error) => Err(RunError {
+                error,
+                results: self.results,
+            }),
+        }
+    }
+
+    /// JUnit XML of the run, one testcase per action: completed ones, the failed one
+    /// with the error, and the ones which never ran as skipped. `on_failure` and
+    /// `finally` handlers follow, in the `<suite>.handlers` class
+    pub fn to_junit(&self, suite: &str) -> String {
+        let mut cases = self.results.iter().map(Case::from).collect::<Vec<_>>();
+        if let Some(ref failed) = self.failed {
+            let mut case = Case::from(failed);
+            if let Some(ref error) = self.error {
+                case.outcome = Outcome::Failed(error.to_string());
+            }
+            cases.push(case);
+        }
+        cases.extend(self.pending.iter().map(|name| Case {
+            name,
+            result: None,
+            outcome: Outcome::Skipped("not run".to_string()),
+            handler: false,
+        }));
+        let mut errors = self.handler_errors.iter();
+        cases.extend(self.handlers.iter().map(|result| {
+            let mut case = Case::from(result);
+            if result.status == ActionStatus::Failed {
+                if let Some(error) = errors.find(|error| error.action() == result.name) {
+                    case.outcome = Outcome::Failed(error.to_string());
+                }
+            }
+            case.handler = true;
+            case
+        }));
+        junit(suite, &cases)
+    }
+}
+
+/// JUnit XML of action results, one testcase per action
+pub fn junit_xml(suite: &str, results: &[ActionResult]) -> String {
+    junit(suite, &results.iter().map(Case::from).collect::<Vec<_>>())
+}
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+struct Case<'a> {
+    name: &'a str,
+    result: Option<&'a ActionResult>,
+    outcome: Outcome,
+    handler: bool,
+}
+
+impl<'a> From<&'a ActionResult> for Case<'a> {
+    fn from(result: &'a ActionResult) -> Self {
+        let outcome = match result.status {
+            ActionStatus::Failed => Outcome::Failed(match result.run {
+                Some(ref run) => format!("command returned exit code '{}'", run.code),
+                None => "failed".to_string(),
+            }),
+            ActionStatus::Skipped => Outcome::Skipped("skipped".to_string()),
+            ActionStatus::Cancelled => Outcome::Skipped("cancelled".to_string()),
+            ActionStatus::Ok
+            | ActionStatus::IgnoredFailure
+            | ActionStatus::Planned
+            | ActionStatus::WouldRun => Outcome::Passed,
+        };
+        Self {
+            name: &result.name,
+            result: Some(result),
+            outcome,
+            handler: false,
+        }
+    }
+}
+
+impl Case<'_> {
+    fn seconds(&self) -> f64 {
+        self.result
+            .and_then(|result| result.timing.as_ref())
+            .map_or(0.0, |timing| timing.duration_ms as f64 / 1000.0)
+    }
+}
+
+fn junit(suite: &str, cases: &[Case<'_>]) -> String {
+    let count = |f: fn(&Outcome) -> bool| cases.iter().filter(|case| f(&case.outcome)).count();
+    let failures = count(|o| matches!(o, Outcome::Failed(_)));
+    let skipped = count(|o| matches!(o, Outcome::Skipped(_)));
+    let time = cases.iter().map(Case::seconds).sum::<f64>();
+    let timestamp = cases
+        .iter()
+        .find_map(|case| case.result.and_then(|result| result.timing.as_ref()))
+        .map(|timing| format!(r#" timestamp="{}""#, escape(&timing.started_at)))
+        .unwrap_or_default();
+    let suite = escape(suite);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        r#"<testsuites name="{suite}" tests="{}" failures="{failures}" errors="0" skipped="{skipped}" time="{time:.3}">"#,
+        cases.len()
+    );
+    let _ = writeln!(
+        xml,
+        r#"  <testsuite name="{suite}" tests="{}" failures="{failures}" errors="0" skipped="{skipped}" time="{time:.3}"{timestamp}>"#,
+        cases.len()
+    );
+    for case in cases {
+        let _ = write!(
+            xml,
+            r#"    <testcase name="{}" classname="{suite}{}" time="{:.3}""#,
+            escape(case.name),
+            if case.handler { ".handlers" } else { "" },
+            case.seconds()
+        );
+        let run = case.result.and_then(|result| result.run.as_ref());
+        let mut body = String::new();
+        match case.outcome {
+            Outcome::Passed => {}
+            Outcome::Failed(ref message) => {
+                let _ = writeln!(
+                    body,
+                    r#"      <failure message="{}" type="{}">{}</failure>"#,
+                    escape(message),
+                    run.map_or("error".to_string(), |run| format!("exit code {}", run.code)),
+                    escape(run.map_or("", |run| run.err.as_str()))
+                );
+            }
+            Outcome::Skipped(ref message) => {
+                let _ = writeln!(body, r#"      <skipped message="{}"/>"#, escape(message));
+            }
+        }
+        if let Some(run) = run {
+            if !run.out.is_empty() {
+                let _ = writeln!(body, "      <system-out>{}</system-out>", escape(&run.out));
+            }
+            if !run.err.is_empty() && !matches!(case.outcome, Outcome::Failed(_)) {
+                let _ = writeln!(body, "      <system-err>{}</system-err>", escape(&run.err));
+            }
+        }
+        if body.is_empty() {
+            xml.push_str("/>\n");
+        } else {
+            let _ = write!(xml, ">\n{body}    </testcase>\n");
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// escape text for XML attributes and content, dropping terminal color sequences
+/// and other characters XML cannot hold
+fn escape(text: &str) -> String {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    let ansi = ANSI.get_or_init(|| Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").unwrap());
+    ansi.replace_all(text, "")
+        .chars()
+        .filter(|&c| matches!(c, '\t' | '\n' | '\r') || c >= ' ')
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+/// Version of the [`JsonReport`] schema, bumped on incompatible changes
+pub const JSON_REPORT_VERSION: u32 = 1;
+
+///
+/// Stable JSON form of a run, for tools reading run outcomes
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonReport {
+    /// schema version, see [`JSON_REPORT_VERSION`]
+    pub version: u32,
+    /// did all actions complete
+    pub ok: bool,
+    /// the error which stopped the run, if any
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// name of the action which failed, if any
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_action: Option<String>,
+    /// results of the actions which ran, in order, the failed one last
+    pub actions: Vec<ActionResult>,
+    /// names of the actions which never ran because of the error
+    #[serde(default)]
+    pub pending: Vec<String>,
+    /// results of the `on_failure` and `finally` handlers which ran, in order
+    #[serde(default)]
+    pub handlers: Vec<ActionResult>,
+    /// errors of handlers
+    #[serde(default)]
+    pub handler_errors: Vec<String>,
+}
+
+impl JsonReport {
+    /// report of action results, ok unless one of them failed
+    pub fn from_results(results: &[ActionResult]) -> Self {
+        let failed = results
+            .iter()
+            .find(|result| result.status == ActionStatus::Failed);
+        Self {
+            version: JSON_REPORT_VERSION,
+            ok: failed.is_none(),
+            error: None,
+            failed_action: failed.map(|result| result.name.clone()),
+            actions: results.to_vec(),
+            pending: vec![],
+            handlers: vec![],
+            handler_errors: vec![],
+        }
+    }
+
+    /// Serialize as pretty printed JSON
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if serialization fails
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl From<&RunReport> for JsonReport {
+    fn from(report: &RunReport) -> Self {
+        Self {
+            version: JSON_REPORT_VERSION,
+            ok: report.is_ok(),
+            error: report.error.as_ref().map(ToString::to_string),
+            failed_action: report.failed_action().map(ToString::to_string),
+            actions: report
+                .results
+                .iter()
+                .chain(report.failed.iter())
+                .cloned()
+                .collect(),
+            pending: report.pending.clone(),
+            handlers: report.handlers.clone(),
+            handler_errors: report
+                .handler_errors
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_snapshot;
+    use pretty_assertions::assert_eq;
+
+    fn report() -> RunReport {
+        let results: Vec<ActionResult> = serde_yaml::from_str(
+            r#"
+- name: install
+  run:
+    script: make install
+    code: 0
+    out: "installed <all>\n"
+    err: ""
+    timing:
+      started_at: "2024-05-01T10:00:00.000Z"
+      ended_at: "2024-05-01T10:00:01.250Z"
+      duration_ms: 1250
+  response: None
+  status: ok
+  timing:
+    started_at: "2024-05-01T10:00:00.000Z"
+    ended_at: "2024-05-01T10:00:01.250Z"
+    duration_ms: 1250
+- name: docs
+  run: ~
+  response: None
+  status: skipped
+- name: deploy
+  run:
+    script: ./deploy.sh
+    code: 2
+    out: ""
+    err: "\u001b[31mno \"prod\" & no staging\u001b[0m\n"
+  response: None
+  status: failed
+  timing:
+    started_at: "2024-05-01T10:00:01.250Z"
+    ended_at: "2024-05-01T10:00:01.750Z"
+    duration_ms: 500
+"#,
+        )
+        .unwrap();
+        let mut results = results.into_iter();
+        RunReport {
+            results: results.by_ref().take(2).collect(),
+            error: Some(ActionError::ScriptFailed {
+                action: "deploy".to_string(),
+                code: 2,
+                stderr: String::new(),
+            }),
+            failed: results.next(),
+            pending: vec!["notify".to_string()],
+            handlers: serde_yaml::from_str(
+                r#"
+- name: rollback
+  run:
+    script: ./rollback.sh
+    code: 1
+    out: ""
+    err: "cannot roll back\n"
+  response: None
+  status: failed
+- name: cleanup
+  run: ~
+  response: None
+  status: ok
+"#,
+            )
+            .unwrap(),
+            handler_errors: vec![ActionError::ScriptFailed {
+                action: "rollback".to_string(),
+                code: 1,
+                stderr: "cannot roll back\n".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_junit() {
+        assert_snapshot!(report().to_junit("provisioning"));
+    }
+
+    #[test]
+    fn test_junit_results() {
+        let report = report();
+        let results = report
+            .results
+            .iter()
+            .chain(report.failed.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        let xml = junit_xml("provisioning", &results);
+        assert!(xml.contains(r#"tests="3" failures="1" errors="0" skipped="1""#));
+        assert!(xml.contains(
+            r#"<failure message="command returned exit code &apos;2&apos;" type="exit code 2">"#
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        let json = JsonReport::from(&report()).to_json().unwrap();
+        let parsed: JsonReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.version, JSON_REPORT_VERSION);
+        assert_eq!(parsed.failed_action.as_deref(), Some("deploy"));
+        assert_eq!(parsed.actions.len(), 3);
+        assert_snapshot!(json);
+    }
+}
@@ -0,0 +1,434 @@
+//!
+//! Templating for run scripts, prompts, options and defaults
+//!
+//! Templates use [minijinja](https://docs.rs/minijinja) syntax, and see the variables
+//! captured so far in the [`VarBag`]:
+//!
+//! ```text
+//! echo {{ city | upper }}
+//! echo {{ transport | default("bus") }}
+//! {% if confirm == "true" %}echo confirmed{% endif %}
+//! mkdir {{ project | kebab_case | shell_quote }}
+//! ```
+//!
+//! On top of the minijinja builtins (`upper`, `lower`, `trim`, `default`, ...) there are
+//! `snake_case`, `kebab_case` and `shell_quote` filters.
+//!
+//! Text with literal `{{`, such as a Go template passed to `docker`, goes in a raw block,
+//! or the whole script is run as is with `raw: true` on the action:
+//!
+//! ```text
+//! docker ps --format {% raw %}'{{.ID}}'{% endraw %}
+//! ```
+//!
+//! Comments are written `{{# ... #}}` rather than minijinja's `{# ... #}`, which would
+//! clash with shell code such as `${#arr[@]}`.
+//!
+//! A bare `{{name}}` naming a variable with `-` or `.` in it, such as `{{my-var}}`, is
+//! looked up as is, as it was before templates, rather than read as an expression.
+//!
+use crate::data::{DefaultValue, Interaction, VarBag};
+use anyhow::Result;
+use minijinja::syntax::SyntaxConfig;
+use minijinja::{Environment, UndefinedBehavior, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+///
+/// Renders templates against a [`VarBag`]
+///
+pub struct Renderer {
+    env: Environment<'static>,
+    shell_env: Environment<'static>,
+}
+
+/// strict: a variable which is not set is an error
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in text.chars() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_numeric();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// `myProject name` -> `my_project_name`
+pub fn snake_case(text: &str) -> String {
+    words(text).join("_")
+}
+
+/// `myProject name` -> `my-project-name`
+pub fn kebab_case(text: &str) -> String {
+    words(text).join("-")
+}
+
+/// bare `{{name}}` references in `template` where `name` has `-` or `.` in it
+fn legacy_names(template: &str) -> Vec<(Range<usize>, &str)> {
+    let mut found = vec![];
+    let mut from = 0;
+    while let Some(start) = template[from..].find("{{").map(|i| from + i) {
+        from = start + 2;
+        let Some(end) = template[from..].find("}}").map(|i| from + i) else {
+            break;
+        };
+        let name = template[from..end].trim();
+        let segments = name.split(['-', '.']).collect::<Vec<_>>();
+        let legacy = segments.len() > 1
+            && segments[0].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && segments.iter().all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+        if legacy {
+            found.push((start..end + 2, name));
+            from = end + 2;
+        }
+    }
+    found
+}
+
+/// replace the legacy references accepted by `keep` with placeholder variables,
+/// returning the rewritten template and the name behind each placeholder
+fn rewrite_legacy(
+    template: &str,
+    keep: impl Fn(&str) -> bool,
+) -> (String, BTreeMap<String, String>) {
+    let mut placeholders = BTreeMap::new();
+    let mut out = String::with_capacity(template.len());
+    let mut last = 0;
+    for (range, name) in legacy_names(template) {
+        if !keep(name) {
+            continue;
+        }
+        let next = placeholders.len();
+        let placeholder = placeholders
+            .entry(name.to_string())
+            .or_insert_with(|| format!("__legacy_{next}"));
+        out.push_str(&template[last..range.start]);
+        out.push_str(&format!("{{{{ {placeholder} }}}}"));
+        last = range.end;
+    }
+    out.push_str(&template[last..]);
+    let placeholders = placeholders
+        .into_iter()
+        .map(|(name, placeholder)| (placeholder, name))
+        .collect();
+    (out, placeholders)
+}
+
+/// quote `text` so a POSIX shell reads it back as a single word
+pub fn shell_quote(text: &str) -> String {
+    let safe = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,@%+".contains(c));
+    if safe {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+impl Renderer {
+    /// create a renderer. When `strict`, referring to a variable which is not
+    /// set is an error, otherwise it renders as an empty string, which in a run
+    /// script can turn `rm -rf /{{dir}}` into `rm -rf /`.
+    pub fn new(strict: bool) -> Self {
+        let env = Self::environment(strict);
+        let mut shell_env = Self::environment(strict);
+        shell_env.set_formatter(|out, _state, value| {
+            if value.is_safe() {
+                write!(out, "{value}")?;
+            } else {
+                out.write_str(&shell_quote(&value.to_string()))?;
+            }
+            Ok(())
+        });
+        Self { env, shell_env }
+    }
+
+    fn environment(strict: bool) -> Environment<'static> {
+        let mut env = Environment::new();
+        env.set_syntax(
+            SyntaxConfig::builder()
+                .comment_delimiters("{{#", "#}}")
+                .build()
+                .expect("valid template syntax"),
+        );
+        env.set_keep_trailing_newline(true);
+        env.set_undefined_behavior(if strict {
+            UndefinedBehavior::Strict
+        } else {
+            UndefinedBehavior::Lenient
+        });
+        env.add_filter("snake_case", |s: String| snake_case(&s));
+        env.add_filter("kebab_case", |s: String| kebab_case(&s));
+        env.add_filter("shell_quote", |s: String| {
+            Value::from_safe_string(shell_quote(&s))
+        });
+        env
+    }
+
+    /// Render a template
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the template is malformed, or
+    /// refers to a missing variable in strict mode
+    pub fn render(&self, template: &str, varbag: &VarBag) -> Result<String> {
+        Self::render_with(&self.env, template, varbag)
+    }
+
+    /// Render a template for a shell script, quoting every interpolated value
+    /// so it is read back by the shell as a single word. Values already passed
+    /// through `shell_quote`, or marked `safe`, are left as is.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the template is malformed, or
+    /// refers to a missing variable in strict mode
+    pub fn render_shell(&self, template: &str, varbag: &VarBag) -> Result<String> {
+        Self::render_with(&self.shell_env, template, varbag)
+    }
+
+    fn render_with(env: &Environment<'_>, template: &str, varbag: &VarBag) -> Result<String> {
+        if !template.contains("{{") && !template.contains("{%") {
+            return Ok(template.to_string());
+        }
+        let (template, placeholders) = rewrite_legacy(template, |name| varbag.contains_key(name));
+        let mut ctx = varbag
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        for (placeholder, name) in &placeholders {
+            ctx.insert(placeholder, &varbag[name]);
+        }
+        env.render_str(&template, ctx)
+            .map_err(|e| anyhow::anyhow!("{e}"))
+    }
+
+    /// Names of the variables a template refers to, without the ones it sets itself
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the template is malformed
+    pub fn variables(&self, template: &str) -> Result<Vec<String>> {
+        if !template.contains("{{") && !template.contains("{%") {
+            return Ok(vec![]);
+        }
+        let undeclared = |template: &str| {
+            self.env
+                .template_from_str(template)
+                .map(|t| t.undeclared_variables(false))
+                .map_err(|e| anyhow::anyhow!("{e}"))
+        };
+        // a legacy name is only a lookup when the template does not declare its
+        // first part itself, as with `{{ loop.index }}` in a for loop
+        let outer = undeclared(template)?;
+        let (template, placeholders) = rewrite_legacy(template, |name| {
+            outer.contains(name.split(['-', '.']).next().unwrap_or(name))
+        });
+        let vars = undeclared(&template)?
+            .into_iter()
+            .map(|var| placeholders.get(&var).cloned().unwrap_or(var))
+            .filter(|var| self.env.globals().all(|(name, _)| name != var))
+            .collect::<BTreeSet<_>>();
+        Ok(vars.into_iter().collect())
+    }
+
+    /// Render every templated part of an interaction: prompt, options and default
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the templates fail to render
+    pub fn interaction(&self, interaction: &Interaction, varbag: &VarBag) -> Result<Interaction> {
+        Ok(Interaction {
+            prompt: self.render(&interaction.prompt, varbag)?,
+            options: interaction
+                .options
+                .as_ref()
+                .map(|options| {
+                    options
+                        .iter()
+                        .map(|option| self.render(option, varbag))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .transpose()?,
+            default_value: match interaction.default_value {
+                Some(DefaultValue::Input(ref input)) => {
+                    Some(DefaultValue::Input(self.render(input, varbag)?))
+                }
+                ref other => other.clone(),
+            },
+            ..interaction.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn varbag() -> VarBag {
+        [("city", "Tel Aviv"), ("project", "myProject name")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let renderer = Renderer::default();
+        let v = varbag();
+        assert_eq!(
+            renderer.render("echo {{city}}\n", &v).unwrap(),
+            "echo Tel Aviv\n"
+        );
+        assert_eq!(
+            renderer
+                .render("{{ city | upper }} {{ city | lower }}", &v)
+                .unwrap(),
+            "TEL AVIV tel aviv"
+        );
+        assert_eq!(
+            renderer
+                .render("{{ project | snake_case }} {{ project | kebab_case }}", &v)
+                .unwrap(),
+            "my_project_name my-project-name"
+        );
+        assert_eq!(
+            renderer.render("cd {{ city | shell_quote }}", &v).unwrap(),
+            "cd 'Tel Aviv'"
+        );
+        assert_eq!(
+            renderer
+                .render(r#"{{ missing | default("dallas") }}"#, &v)
+                .unwrap(),
+            "dallas"
+        );
+        assert_eq!(
+            renderer
+                .render(
+                    r#"{% if city == "Tel Aviv" %}yes{% else %}no{% endif %}"#,
+                    &v
+                )
+                .unwrap(),
+            "yes"
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let renderer = Renderer::default();
+        let v = varbag();
+        assert!(renderer.render("rm -rf /{{missing}}", &v).is_err());
+        assert_eq!(
+            renderer
+                .render(r#"{{ missing | default("x") }}"#, &v)
+                .unwrap(),
+            "x"
+        );
+    }
+
+    #[test]
+    fn test_lenient() {
+        let renderer = Renderer::new(false);
+        let v = varbag();
+        assert_eq!(renderer.render("echo {{missing}}", &v).unwrap(), "echo ");
+    }
+
+    #[test]
+    fn test_variables() {
+        let renderer = Renderer::default();
+        assert_eq!(
+            renderer
+                .variables("{% for x in range(3) %}{{ x }}{{ city | upper }}{% endfor %}{{ b }}")
+                .unwrap(),
+            vec!["b", "city"]
+        );
+        assert!(renderer.variables("{{ city").is_err());
+    }
+
+    #[test]
+    fn test_render_shell() {
+        let renderer = Renderer::default();
+        let v = varbag();
+        assert_eq!(
+            renderer
+                .render_shell("cd {{ city }} && echo {{ project | shell_quote }}", &v)
+                .unwrap(),
+            "cd 'Tel Aviv' && echo 'myProject name'"
+        );
+    }
+
+    #[test]
+    fn test_shell_comments() {
+        let renderer = Renderer::default();
+        let v = varbag();
+        assert_eq!(
+            renderer
+                .render_shell("arr=(a b); echo ${#arr[@]} {{city}}", &v)
+                .unwrap(),
+            "arr=(a b); echo ${#arr[@]} 'Tel Aviv'"
+        );
+        assert_eq!(
+            renderer
+                .render("{{# a note #}}echo {{ city }}", &v)
+                .unwrap(),
+            "echo Tel Aviv"
+        );
+    }
+
+    #[test]
+    fn test_legacy_names() {
+        let renderer = Renderer::default();
+        let mut v = varbag();
+        v.insert("my-var".to_string(), "a b".to_string());
+        v.insert("build.tag".to_string(), "v1".to_string());
+        assert_eq!(
+            renderer
+                .render_shell("echo {{my-var}} {{ build.tag }} {{my-var}}", &v)
+                .unwrap(),
+            "echo 'a b' v1 'a b'"
+        );
+        assert!(renderer.render("echo {{other-var}}", &v).is_err());
+        assert_eq!(
+            renderer
+                .variables("{{my-var}} {{ build.tag }} {% for x in range(2) %}{{ loop.index }}{% endfor %}")
+                .unwrap(),
+            vec!["build.tag", "my-var"]
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("bus"), "bus");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,554 @@
+//!
+//! Static checks of [`Action`] definitions, to find mistakes before running them
+//!
+//! ```
+//! use interactive_actions::data::Action;
+//! use interactive_actions::validate::validate;
+//!
+//! let actions: Vec<Action> = serde_yaml::from_str(
+//! r#"
+//! - name: transport
+//!   interaction:
+//!     kind: select
+//!     prompt: pick a transport
+//!     options: [bus, train]
+//!     default_value: 2
+//! "#).unwrap();
+//! for diagnostic in validate(&actions) {
+//!     println!("{diagnostic}");
+//! }
+//! ```
+//!
+use crate::condition::Condition;
+use crate::data::{Action, ActionHook, DefaultValue, InteractionKind, VarBag};
+use crate::template::Renderer;
+use crate::workflow::Workflow;
+use crate::FAILURE_VARS;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+///
+/// How bad a [`Diagnostic`] is
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    /// the action will fail or misbehave when run
+    #[serde(rename = "error")]
+    Error,
+
+    /// likely a mistake, but may be intended, e.g. a variable supplied by the host
+    #[serde(rename = "warning")]
+    Warning,
+}
+
+///
+/// Where in the action list a [`Diagnostic`] points to
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    /// index of the action in its list
+    pub index: usize,
+    /// name of the action, empty for workflow defaults
+    pub action: String,
+    /// path to the offending field, e.g. `[2].interaction.default_value`,
+    /// `after[0].on_failure[1].run` or `defaults.env.IMAGE`
+    pub path: String,
+}
+
+///
+/// A problem found in action definitions
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// how bad it is
+    pub severity: Severity,
+    /// where it is
+    pub location: Location,
+    /// what is wrong
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.location.action.is_empty() {
+            return write!(f, "{}: {}: {}", severity, self.location.path, self.message);
+        }
+        write!(
+            f,
+            "{}: {} ('{}'): {}",
+            severity, self.location.path, self.location.action, self.message
+        )
+    }
+}
+
+///
+/// An action to check, and where it is
+///
+struct Entry {
+    /// index of the action in its list
+    index: usize,
+    /// path to the action, e.g. `before[2]` or `[0].on_failure[1]`
+    path: String,
+    /// the action, with its hook set
+    action: Action,
+    /// position among the actions run in order, `None` for failure and cleanup handlers
+    order: Option<usize>,
+    /// an `on_failure` handler, or one nested in it, which sees the `failed_*` variables
+    on_failure: bool,
+}
+
+impl Entry {
+    fn new(index: usize, path: String, action: Action, order: Option<usize>) -> Self {
+        Self {
+            index,
+            path,
+            action,
+            order,
+            on_failure: false,
+        }
+    }
+}
+
+/// `entry`, followed by its failure and cleanup handlers, recursively
+fn with_handlers(entry: Entry, entries: &mut Vec<Entry>) {
+    let lists = [
+        ("on_failure", true, entry.action.on_failure.clone()),
+        ("finally", entry.on_failure, entry.action.finally.clone()),
+    ];
+    let path = entry.path.clone();
+    entries.push(entry);
+    for (list, on_failure, handlers) in lists {
+        for (index, handler) in handlers.into_iter().enumerate() {
+            let entry = Entry {
+                on_failure,
+                ..Entry::new(index, format!("{path}.{list}[{index}]"), handler, None)
+            };
+            with_handlers(entry, entries);
+        }
+    }
+}
+
+struct Diagnostics {
+    list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    fn push(&mut self, severity: Severity, entry: &Entry, field: &str, message: String) {
+        self.list.push(Diagnostic {
+            severity,
+            location: Location {
+                index: entry.index,
+                action: entry.action.name.clone(),
+                path: format!("{}{field}", entry.path),
+            },
+            message,
+        });
+    }
+}
+
+/// variables set by an action
+fn produced(action: &Action) -> Vec<&str> {
+    let mut vars = vec![];
+    if let Some(out) = action.interaction.as_ref().and_then(|i| i.out.as_ref()) {
+        vars.push(out.as_str());
+    }
+    if let Some(ref out) = action.out {
+        vars.extend(
+            [&out.stdout, &out.stderr, &out.code]
+                .into_iter()
+                .filter_map(|var| var.as_deref()),
+        );
+        vars.extend(out.extract.iter().map(|extract| extract.var.as_str()));
+    }
+    vars
+}
+
+/// templated fields of an action, with their paths
+fn templates(action: &Action) -> Vec<(String, &str)> {
+    let mut fields = vec![];
+    if let Some(ref run) = action.run {
+        if action.raw != Some(true) {
+            fields.push((".run".to_string(), run.as_str()));
+        }
+    }
+    if let Some(ref dir) = action.working_dir {
+        fields.push((".working_dir".to_string(), dir.as_str()));
+    }
+    for (name, value) in &action.env {
+        fields.push((format!(".env.{name}"), value.as_str()));
+    }
+    if let Some(ref interaction) = action.interaction {
+        fields.push((
+            ".interaction.prompt".to_string(),
+            interaction.prompt.as_str(),
+        ));
+        for (i, option) in interaction.options.iter().flatten().enumerate() {
+            fields.push((format!(".interaction.options[{i}]"), option.as_str()));
+        }
+        if let Some(DefaultValue::Input(ref input)) = interaction.default_value {
+            fields.push((".interaction.default_value".to_string(), input.as_str()));
+        }
+    }
+    fields
+}
+
+fn check_interaction(diagnostics: &mut Diagnostics, entry: &Entry) {
+    let Some(ref interaction) = entry.action.interaction else {
+        return;
+    };
+    let options = interaction.options.as_deref().unwrap_or_default();
+    let has_options = matches!(
+        interaction.kind,
+        InteractionKind::Select | InteractionKind::MultiSelect
+    );
+    if has_options && options.is_empty() {
+        diagnostics.push(
+            Severity::Error,
+            entry,
+            ".interaction.options",
+            format!("{:?} needs a non-empty list of options", interaction.kind),
+        );
+    } else if !has_options && interaction.options.is_some() {
+        diagnostics.push(
+            Severity::Warning,
+            entry,
+            ".interaction.options",
+            format!("options are ignored for {:?}", interaction.kind),
+        );
+    }
+
+    let Some(ref default) = interaction.default_value else {
+        return;
+    };
+    let out_of_range = |i: &usize| *i >= options.len();
+    let problem = match (&interaction.kind, default) {
+        (InteractionKind::Input | InteractionKind::Password, DefaultValue::Input(_))
+        | (InteractionKind::Confirm, DefaultValue::Confirm(_)) => None,
+        (InteractionKind::Select, DefaultValue::Select(i)) if out_of_range(i) => Some(format!(
+            "default index {} is out of range, there are {} options",
+            i,
+            options.len()
+        )),
+        (InteractionKind::MultiSelect, DefaultValue::MultiSelect(indices)) => {
+            indices.iter().find(|i| out_of_range(i)).map(|i| {
+                format!(
+                    "default index {} is out of range, there are {} options",
+                    i,
+                    options.len()
+                )
+            })
+        }
+        (InteractionKind::Select, DefaultValue::Select(_)) => None,
+        (kind, default) => {
+            let expected = match kind {
+                InteractionKind::Input | InteractionKind::Password => "a string",
+                InteractionKind::Confirm => "a boolean",
+                InteractionKind::Select => "an option index",
+                InteractionKind::MultiSelect => "a list of option indices",
+            };
+            Some(format!(
+                "default for {kind:?} should be {expected}, got {default:?}"
+            ))
+        }
+    };
+    if let Some(message) = problem {
+        diagnostics.push(
+            Severity::Error,
+            entry,
+            ".interaction.default_value",
+            message,
+        );
+    }
+}
+
+///
+/// Check action definitions, and their `on_failure` and `finally` handlers, for:
+/// * duplicate action names
+/// * select and multiselect interactions without options, or with default indices out of range
+/// * default values which do not match the kind of interaction
+/// * malformed `when` expressions and templates
+/// * variables which are never set, or only set by actions which run later,
+///   including `before` actions relying on variables set by `after` actions
+///
+/// Variables can also be supplied by the host, so problems with variables are warnings.
+///
+pub fn validate(actions: &[Action]) -> Vec<Diagnostic> {
+    let mut entries = vec![];
+    for (index, action) in actions.iter().enumerate() {
+        let entry = Entry::new(index, format!("[{index}]"), action.clone(), Some(index));
+        with_handlers(entry, &mut entries);
+    }
+    check(&entries, &BTreeMap::new(), &VarBag::new())
+}
+
+///
+/// Check a [`Workflow`] like [`validate`], taking the variables it sets as set.
+/// Locations point into the workflow document, e.g. `before[2].run` or `defaults.env.IMAGE`.
+///
+pub fn validate_workflow(workflow: &Workflow) -> Vec<Diagnostic> {
+    let lists = [
+        ("before", Some(ActionHook::Before), &workflow.before),
+        ("actions", None, &workflow.actions),
+        ("after", Some(ActionHook::After), &workflow.after),
+    ];
+    let mut entries = vec![];
+    let mut order = 0;
+    for (list, hook, actions) in lists {
+        for (index, action) in actions.iter().enumerate() {
+            let action = Action {
+                hook: hook.clone().unwrap_or_else(|| action.hook.clone()),
+                ..action.clone()
+            };
+            let entry = Entry::new(index, format!("{list}[{index}]"), action, Some(order));
+            with_handlers(entry, &mut entries);
+            order += 1;
+        }
+    }
+    for (list, on_failure, handlers) in [
+        ("on_failure", true, &workflow.on_failure),
+        ("finally", false, &workflow.finally),
+    ] {
+        for (index, handler) in handlers.iter().enumerate() {
+            let entry = Entry {
+                on_failure,
+                ..Entry::new(index, format!("{list}[{index}]"), handler.clone(), None)
+            };
+            with_handlers(entry, &mut entries);
+        }
+    }
+    check(&entries, &workflow.defaults.env, &workflow.vars)
+}
+
+fn check(
+    entries: &[Entry],
+    defaults_env: &BTreeMap<String, String>,
+    vars: &VarBag,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Diagnostics { list: vec![] };
+    let renderer = Renderer::default();
+
+    // where every variable is set, as (hook, position in run order)
+    let mut producers: BTreeMap<&str, Vec<(&ActionHook, Option<usize>)>> = BTreeMap::new();
+    for entry in entries {
+        for var in produced(&entry.action) {
+            producers
+                .entry(var)
+                .or_default()
+                .push((&entry.action.hook, entry.order));
+        }
+    }
+
+    // default env templates render for every action, ahead of all of them
+    for (name, template) in defaults_env {
+        let path = format!("defaults.env.{name}");
+        let problems = match renderer.variables(template) {
+            Ok(used) => used
+                .into_iter()
+                .filter(|var| !vars.contains_key(var))
+                .map(|var| match producers.get(var.as_str()) {
+                    None => format!("variable '{var}' is never set by any action"),
+                    Some(_) => format!("variable '{var}' is only set by actions which run later"),
+                })
+                .map(|message| (Severity::Warning, message))
+                .collect::<Vec<_>>(),
+            Err(e) => vec![(Severity::Error, format!("invalid template: {e}"))],
+        };
+        for (severity, message) in problems {
+            diagnostics.list.push(Diagnostic {
+                severity,
+                location: Location {
+                    index: 0,
+                    action: String::new(),
+                    path: path.clone(),
+                },
+                message,
+            });
+        }
+    }
+
+    let mut names = BTreeSet::new();
+    for entry in entries {
+        let action = &entry.action;
+        if entry.order.is_some() && !names.insert(action.name.as_str()) {
+            diagnostics.push(
+                Severity::Error,
+                entry,
+                ".name",
+                format!("duplicate action name '{}'", action.name),
+            );
+        }
+
+        check_interaction(&mut diagnostics, entry);
+
+        let mut used = vec![];
+        if let Some(ref when) = action.when {
+            match Condition::parse(when) {
+                Ok(condition) => used.extend(
+                    condition
+                        .variables()
+                        .into_iter()
+                        .map(|var| (".when".to_string(), var.to_string())),
+                ),
+                Err(e) => diagnostics.push(
+                    Severity::Error,
+                    entry,
+                    ".when",
+                    format!("invalid expression: {e}"),
+                ),
+            }
+        }
+        for (field, template) in templates(action) {
+            match renderer.variables(template) {
+                Ok(vars) => used.extend(vars.into_iter().map(|var| (field.clone(), var))),
+                Err(e) => diagnostics.push(
+                    Severity::Error,
+                    entry,
+                    &field,
+                    format!("invalid template: {e}"),
+                ),
+            }
+        }
+
+        for (field, var) in used {
+            if vars.contains_key(&var) || (entry.on_failure && FAILURE_VARS.contains(&&*var)) {
+                continue;
+            }
+            // before actions run first, then after actions, each in order. handlers run
+            // once the actions they handle ran, so anything set is taken as available
+            let available = |(hook, i): &(&ActionHook, Option<usize>)| match (entry.order, i) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(index), Some(i)) => {
+                    (*hook == &action.hook && *i < index)
+                        || (action.hook == ActionHook::After && *hook == &ActionHook::Before)
+                }
+            };
+            let message = match producers.get(var.as_str()) {
+                None => format!("variable '{var}' is never set by any action"),
+                Some(sources) if sources.iter().any(available) => continue,
+                Some(sources)
+                    if action.hook == ActionHook::Before
+                        && sources.iter().all(|(hook, _)| *hook == &ActionHook::After) =>
+                {
+                    format!(
+                        "variable '{var}' is only set by 'after' actions, which run after this 'before' action"
+                    )
+                }
+                Some(_) => format!("variable '{var}' is only set by actions which run later"),
+            };
+            diagnostics.push(Severity::Warning, entry, &field, message);
+        }
+    }
+    diagnostics.list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_debug_snapshot;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_validate() {
+        let actions: Vec<Action> = serde_yaml::from_str(
+            r#"
+- name: select-action
+  interaction:
+    kind: select
+    prompt: select transport
+    options: [bus, train]
+    default_value: 2
+    out: transport
+- name: empty-select
+  interaction:
+    kind: select
+    prompt: "{{ city }}?"
+- name: select-action
+  when: transport == "bus" and
+  interaction:
+    kind: confirm
+    prompt: sure?
+    default_value: yes
+- name: multiselect-action
+  interaction:
+    kind: multiselect
+    prompt: languages
+    options: [rust, go]
+    default_value: [0, 5]
+    out: langs
+  run: echo {{ langs }} {{ branch }} {{ missing
+- name: setup
+  hook: before
+  run: echo {{ transport }}
+- name: branch
+  run: git branch --show-current
+  out:
+    stdout: branch
+- name: valid
+  when: langs is not empty
+  run: echo {{ branch | upper }}
+"#,
+        )
+        .unwrap();
+        assert_debug_snapshot!(validate(&actions)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_validate_workflow() {
+        let workflow: Workflow = serde_yaml::from_str(
+            r#"
+vars:
+  registry: ghcr.io
+defaults:
+  env:
+    IMAGE: "{{ registry }}/{{ image }}"
+before:
+- name: check
+  run: git diff --quiet
+- name: tag
+  run: git describe
+  out:
+    stdout: tag
+after:
+- name: build
+  run: docker build -t {{ registry }}:{{ tag }} .
+  on_failure:
+  - name: report
+    run: echo {{ failed_error }}
+  - name: notify
+    run: notify {{ channel }}
+    finally:
+    - name: notified
+      when: failed_action == "build"
+      run: echo {{ {{ }}
+on_failure:
+- name: rollback
+  run: echo {{ failed_action }} {{ tag }}
+finally:
+- name: cleanup
+  run: rm -rf {{ failed_stderr }}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            validate_workflow(&workflow)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "warning: defaults.env.IMAGE: variable 'image' is never set by any action",
+                "warning: after[0].on_failure[1].run ('notify'): variable 'channel' is never set by any action",
+                "error: after[0].on_failure[1].finally[0].run ('notified'): invalid template: syntax error: unexpected `}`, expected `:` (in <string>:1)",
+                "warning: finally[0].run ('cleanup'): variable 'failed_stderr' is never set by any action",
+            ]
+        );
+    }
+}
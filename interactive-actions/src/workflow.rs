@@ -0,0 +1,346 @@
+//!
+//! Workflows: a whole generator in one document, with its metadata, initial
+//! variables, default settings and actions
+//!
+//! ```no_run
+//! use interactive_actions::data::{ActionHook, VarBag};
+//! use interactive_actions::workflow::Workflow;
+//! use interactive_actions::ActionRunner;
+//!
+//! let workflow: Workflow = serde_yaml::from_str(
+//! r#"
+//! name: service
+//! description: scaffold a service
+//! version: "1.2"
+//! vars:
+//!   registry: ghcr.io
+//! defaults:
+//!   shell: bash -eu -c
+//!   env:
+//!     RUST_LOG: info
+//! before:
+//! - name: check
+//!   run: git diff --quiet
+//! after:
+//! - name: city
+//!   interaction:
+//!     kind: input
+//!     prompt: which city?
+//!     out: city
+//! - name: build
+//!   run: docker build -t {{registry}}/{{city}} .
+//! finally:
+//! - name: cleanup
+//!   run: docker image prune -f
+//! "#).unwrap();
+//! let mut runner = ActionRunner::default();
+//! let mut v = VarBag::new();
+//! runner.run_workflow(&workflow, None, &mut v, ActionHook::After);
+//! ```
+//!
+use crate::data::{default, Action, ActionHook, Capture, VarBag};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+///
+/// Settings of every action of a [`Workflow`] which does not set its own
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    /// shell of actions which do not set one, see [`Action::shell`]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+
+    /// environment variables of every run script. an action setting the same variable wins
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// capture mode of actions which do not set one, see [`Action::capture`]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<Capture>,
+}
+
+impl Defaults {
+    /// a copy of `action` with these defaults filled in, and in its `on_failure`
+    /// and `finally` handlers, recursively
+    pub fn apply(&self, action: &Action) -> Action {
+        let mut action = action.clone();
+        if action.shell.is_none() {
+            action.shell.clone_from(&self.shell);
+        }
+        for (name, value) in &self.env {
+            action
+                .env
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        if action.capture.is_none() {
+            action.capture = self.capture;
+        }
+        for handler in action
+            .on_failure
+            .iter_mut()
+            .chain(action.finally.iter_mut())
+        {
+            *handler = self.apply(handler);
+        }
+        action
+    }
+}
+
+///
+/// A whole workflow: metadata, initial variables, default settings, and actions.
+/// Actions run in their hook, and `before` and `after` list actions for each hook
+/// without setting `hook` on every one of them.
+///
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Workflow {
+    /// name of the workflow
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+
+    /// what the workflow does
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// version of the workflow
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// variables set before the first action, unless the host set them already
+    #[serde(default)]
+    #[serde(skip_serializing_if = "VarBag::is_empty")]
+    pub vars: VarBag,
+
+    /// settings of every action which does not set its own
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub defaults: Defaults,
+
+    /// actions, each run in the hook it sets
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<Action>,
+
+    /// actions run in the `before` hook, ahead of `before` actions in `actions`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<Action>,
+
+    /// actions run in the `after` hook, following `after` actions in `actions`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<Action>,
+
+    /// actions to run when the run of their hook stops on a failure or a cancel,
+    /// see [`Action::on_failure`]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_failure: Vec<Action>,
+
+    /// actions to run at the end of the run of their hook, whether it failed or not.
+    /// they are `after` actions unless they set `hook: before`, so running the `before`
+    /// and then the `after` hook runs each of them once, after the `after` actions
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub finally: Vec<Action>,
+}
+
+impl From<Vec<Action>> for Workflow {
+    fn from(actions: Vec<Action>) -> Self {
+        Self {
+            actions,
+            ..Self::default()
+        }
+    }
+}
+
+impl Workflow {
+    /// all actions, with their hook set and defaults filled in: `before`, then `actions`, then `after`
+    pub fn all_actions(&self) -> Vec<Action> {
+        let hooked = |hook: ActionHook| {
+            move |action: &Action| Action {
+                hook: hook.clone(),
+                ..action.clone()
+            }
+        };
+        self.before
+            .iter()
+            .map(hooked(ActionHook::Before))
+            .chain(self.actions.iter().cloned())
+            .chain(self.after.iter().map(hooked(ActionHook::After)))
+            .map(|action| self.defaults.apply(&action))
+            .collect()
+    }
+
+    /// `on_failure` actions, with defaults filled in
+    pub fn on_failure_actions(&self) -> Vec<Action> {
+        self.on_failure
+            .iter()
+            .map(|action| self.defaults.apply(action))
+            .collect()
+    }
+
+    /// `finally` actions, with defaults filled in
+    pub fn finally_actions(&self) -> Vec<Action> {
+        self.finally
+            .iter()
+            .map(|action| self.defaults.apply(action))
+            .collect()
+    }
+
+    /// set the workflow variables in `varbag`, except the ones set already
+    pub fn seed(&self, varbag: &mut VarBag) {
+        for (name, value) in &self.vars {
+            if !varbag.contains_key(name) {
+                varbag.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_all_actions() {
+        let workflow: Workflow = serde_yaml::from_str(
+            r#"
+name: service
+vars:
+  city: tlv
+defaults:
+  shell: bash -c
+  env:
+    LEVEL: info
+    MODE: dev
+  capture: tee
+actions:
+- name: listed
+  hook: before
+  env:
+    MODE: prod
+before:
+- name: first
+  shell: zsh -c
+after:
+- name: last
+  capture: true
+- name: streamed
+  capture: false
+"#,
+        )
+        .unwrap();
+        let actions = workflow.all_actions();
+        let summary = actions
+            .iter()
+            .map(|a| {
+                (
+                    a.name.as_str(),
+                    a.hook.clone(),
+                    a.shell.as_deref().unwrap_or_default(),
+                    a.env.get("MODE").map(String::as_str).unwrap_or_default(),
+                    a.capture.unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("first", ActionHook::Before, "zsh -c", "dev", Capture::Tee),
+                (
+                    "listed",
+                    ActionHook::Before,
+                    "bash -c",
+                    "prod",
+                    Capture::Tee
+                ),
+                ("last", ActionHook::After, "bash -c", "dev", Capture::On),
+                (
+                    "streamed",
+                    ActionHook::After,
+                    "bash -c",
+                    "dev",
+                    Capture::Off
+                ),
+            ]
+        );
+
+        let mut v = VarBag::new();
+        v.insert("other".to_string(), "x".to_string());
+        workflow.seed(&mut v);
+        assert_eq!(v.get("city").map(String::as_str), Some("tlv"));
+        let mut v = VarBag::new();
+        v.insert("city".to_string(), "dallas".to_string());
+        workflow.seed(&mut v);
+        assert_eq!(v.get("city").map(String::as_str), Some("dallas"));
+    }
+
+    #[test]
+    fn test_handler_defaults() {
+        let workflow: Workflow = serde_yaml::from_str(
+            r#"
+defaults:
+  shell: bash -c
+  env:
+    LEVEL: info
+after:
+- name: build
+  on_failure:
+  - name: report
+    finally:
+    - name: notify
+      shell: zsh -c
+on_failure:
+- name: rollback
+finally:
+- name: cleanup
+  finally:
+  - name: prune
+"#,
+        )
+        .unwrap();
+        let summary = |action: &Action| {
+            (
+                action.name.clone(),
+                action.shell.clone().unwrap_or_default(),
+                action.env.get("LEVEL").cloned().unwrap_or_default(),
+            )
+        };
+        let build = &workflow.all_actions()[0];
+        let report = &build.on_failure[0];
+        let cleanup = &workflow.finally_actions()[0];
+        assert_eq!(
+            [
+                report,
+                &report.finally[0],
+                &workflow.on_failure_actions()[0],
+                cleanup,
+                &cleanup.finally[0],
+            ]
+            .map(summary),
+            [
+                ("report", "bash -c", "info"),
+                ("notify", "zsh -c", "info"),
+                ("rollback", "bash -c", "info"),
+                ("cleanup", "bash -c", "info"),
+                ("prune", "bash -c", "info"),
+            ]
+            .map(|(name, shell, level)| (
+                name.to_string(),
+                shell.to_string(),
+                level.to_string()
+            ))
+        );
+    }
+}